@@ -0,0 +1,135 @@
+//! Compares the `lines().filter(contains)` search minigrep started out
+//! with against the memchr-based literal search, both on a string in
+//! memory and on a file, streamed line by line or memory-mapped.
+//!
+//! Run with `cargo bench`. The input is generated, 256 MB by default;
+//! set `MINIGREP_BENCH_MB` for another size.
+
+use std::env;
+use std::fs::File;
+use std::hint::black_box;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use ch12_minigrep::matcher::Literal;
+use ch12_minigrep::{LineMatch, Searcher, Sink};
+
+const WORDS: &[&str] = &[
+    "the", "quick", "brown", "fox", "jumps", "over", "lazy", "dog", "and", "a", "log", "line",
+    "request", "served", "in", "ms", "user", "session", "cache", "miss", "hit",
+];
+
+/// Found on about one line in ten thousand.
+const RARE: &str = "Sherlock";
+/// Found on most lines.
+const COMMON: &str = "the";
+
+const RUNS: usize = 3;
+
+// Counts matches without keeping them.
+#[derive(Default)]
+struct Counter(usize);
+
+impl Sink for Counter {
+    fn matched(&mut self, _found: &LineMatch) -> io::Result<bool> {
+        self.0 += 1;
+        Ok(true)
+    }
+}
+
+fn main() {
+    let megabytes: usize = env::var("MINIGREP_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(256);
+
+    eprintln!("generating {} MB of input...", megabytes);
+    let contents = generate(megabytes << 20);
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+    file.flush().unwrap();
+    let path = file.path();
+
+    println!(
+        "{:<28} {:<9} {:>10} {:>10} {:>9}",
+        "search", "query", "time", "MB/s", "lines"
+    );
+    for query in [RARE, COMMON] {
+        bench("lines().filter(contains)", query, contents.len(), || {
+            contents.lines().filter(|line| line.contains(query)).count()
+        });
+        bench("search", query, contents.len(), || {
+            ch12_minigrep::search(query, &contents).len()
+        });
+        bench("Searcher, streamed file", query, contents.len(), || {
+            streamed(query, path)
+        });
+        bench("Searcher, mapped file", query, contents.len(), || {
+            let mut counter = Counter::default();
+            Searcher::new()
+                .search_path(Literal::new(query), path, &mut counter)
+                .unwrap();
+            counter.0
+        });
+    }
+}
+
+// How `run` searched files before they were mapped.
+fn streamed(query: &str, path: &Path) -> usize {
+    let reader = BufReader::new(File::open(path).unwrap());
+    let mut counter = Counter::default();
+    Searcher::new()
+        .search_reader(Literal::new(query), reader, &mut counter)
+        .unwrap();
+    counter.0
+}
+
+// Prints the best of a few runs, which is the one least disturbed by
+// whatever else the machine was doing.
+fn bench(name: &str, query: &str, bytes: usize, mut search: impl FnMut() -> usize) {
+    let mut best = Duration::MAX;
+    let mut lines = 0;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        lines = black_box(search());
+        best = best.min(started.elapsed());
+    }
+
+    let throughput = bytes as f64 / (1 << 20) as f64 / best.as_secs_f64();
+    println!(
+        "{:<28} {:<9} {:>9.3}s {:>10.0} {:>9}",
+        name,
+        query,
+        best.as_secs_f64(),
+        throughput,
+        lines
+    );
+}
+
+// Lines of pseudo-random words, the same every time.
+fn generate(len: usize) -> String {
+    let mut contents = String::with_capacity(len + 128);
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    while contents.len() < len {
+        if next() % 10_000 == 0 {
+            contents.push_str(RARE);
+            contents.push(' ');
+        }
+        for _ in 0..(8 + next() % 8) {
+            contents.push_str(WORDS[(next() % WORDS.len() as u64) as usize]);
+            contents.push(' ');
+        }
+        contents.pop();
+        contents.push('\n');
+    }
+    contents
+}
@@ -0,0 +1,1501 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::thread;
+
+use encoding_rs::Encoding;
+use regex::{Regex, RegexBuilder};
+
+use crate::encoding;
+use crate::settings::{self, Files, Setting, Source, Value};
+use crate::structured::Field;
+use crate::{
+    FoldOptions, FoldedQuery, FuzzyQuery, Normalization, Pattern, Query, QueryError, QueryOptions,
+};
+
+const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY PATH...
+       minigrep [OPTIONS] (-e PATTERN | -f FILE | --expr EXPR)... PATH...
+       minigrep index DIR...
+
+Search each PATH (files or directories) for lines containing QUERY.
+A PATH of '-' reads standard input. Directories are searched recursively,
+hidden files included, skipping what .gitignore and .ignore files exclude.
+
+'minigrep index' builds a trigram index of each DIR, or updates it for
+the files that changed since. Literal searches of an indexed directory
+then only read the files that can contain a match. To search for the
+word 'index' itself, use 'minigrep -- index PATH...'.
+
+Options:
+      --regex               Treat QUERY as a regular expression
+  -e, --regexp PATTERN      Search for this regex; repeat for several
+  -f, --file FILE           Search for each line of FILE, as literals
+                            unless --regex or -e is also given
+  -F, --fixed-strings       Treat every pattern as a literal string
+      --expr EXPR           Combine terms with AND, OR, NOT and parens,
+                            e.g. 'ERROR AND NOT (timeout OR retry)'
+  -i, --ignore-case         Case-insensitive search (overrides IGNORE_CASE)
+  -s, --case-sensitive      Case-sensitive search (overrides IGNORE_CASE)
+      --turkic              With -i, fold dotted/dotless i the Turkish way
+      --normalize FORM      Normalize query and input to nfc or nfd first,
+                            so composed and decomposed text match
+      --fuzzy K             Also match lines within K typos (Levenshtein
+                            distance) of QUERY, best matches first
+  -r, --replace TEMPLATE    Print matching lines with each match replaced
+                            by TEMPLATE; in regex mode $1, ${name} and so
+                            on refer to capture groups
+      --in-place            With --replace, rewrite the files instead of
+                            printing the lines
+      --backup SUFFIX       With --in-place, keep each original file as
+                            its path plus SUFFIX
+      --csv-column NAME     Treat input as CSV with a header row and only
+                            match the NAME column; prints whole records
+      --json-path PATH      Treat input as JSON Lines and only match the
+                            field at PATH, e.g. .request.status
+  -U, --multiline           Let matches span lines: a literal QUERY may
+                            contain line breaks, and regexes can match \n;
+                            prints every line a match covers
+  -v, --invert-match        Print lines that do NOT match
+  -n, --line-number         Prefix each line with its line number
+  -c, --count               Print only the number of matching lines per file
+      --count-matches       Like -c, but count every match, not just the
+                            lines with one
+  -l, --files-with-matches  Print only the paths of files with a match
+  -q, --quiet               Print nothing and stop at the first match; only
+                            the exit status tells whether there was one
+  -A, --after-context N     Print N lines of context after each match
+  -B, --before-context N    Print N lines of context before each match
+  -C, --context N           Print N lines of context before and after
+  -a, --text                Search binary files as if they were text
+      --encoding LABEL      Decode input from LABEL, e.g. utf-16le or
+                            latin1 (default: UTF-8, or as its BOM says)
+      --follow              Keep FILE open and print new matching lines as
+                            they are appended, like tail -f, reopening it
+                            when the log is rotated or truncated
+  -z, --search-zip          Decompress gzip, zstd and bzip2 input on the
+                            fly, detected by its magic bytes
+      --stats               Finish with how many files, bytes, lines and
+                            matches were searched and found, and how long
+                            it took
+      --json                Print results as JSON Lines (one event per
+                            line), for editors and scripts
+      --color WHEN          Highlight matches: auto, always or never
+                            (default: auto, i.e. only on a terminal)
+  -j, --jobs N              Search up to N files in parallel
+                            (default: number of CPUs)
+      --debug-config        Print every setting from the config files,
+                            environment and command line, and where it
+                            came from, then exit
+      --no-config           Ignore config files and environment variables
+  -h, --help                Print this help and exit
+  -V, --version             Print the version and exit
+      --                    Treat every following argument as positional
+
+Defaults for -i, -s, --turkic, --normalize, -n, -A, -B, -C, -j, --color,
+--stats, -z, --encoding and -a can be set in, from lowest to
+highest precedence: ~/.config/minigrep/config.toml (or under
+$XDG_CONFIG_HOME), the nearest .minigreprc in the current directory or
+above, and MINIGREP_* environment variables; the command line beats them
+all. Files are TOML with one long flag name per key, e.g.
+
+    line-number = true
+    context = 2
+    color = \"never\"
+
+and a variable like MINIGREP_LINE_NUMBER=1 works the same. IGNORE_CASE,
+when set, still turns on -i.
+
+Exit status is 0 if a line was selected, 1 if none was, and 2 if an
+error occurred (unless -q found a match).";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves `Auto` by checking whether stdout is a terminal and
+    /// honoring the `NO_COLOR` convention.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+pub struct Config {
+    pub query: String,
+    pub paths: Vec<String>,
+    pub ignore_case: bool,
+    pub turkic: bool,
+    pub normalization: Option<Normalization>,
+    /// Every pattern given with `-e` or `-f`, or just `query` otherwise.
+    pub patterns: Vec<String>,
+    /// A boolean query expression given with `--expr`.
+    pub expr: Option<String>,
+    // Compiled from `query` in regex mode when there is just one pattern.
+    pub regex: Option<Regex>,
+    // Compiled from `patterns` or `expr` when there is more than one term.
+    pub multi_pattern: Option<Query>,
+    /// The maximum edit distance for a fuzzy search.
+    pub fuzzy: Option<usize>,
+    /// What to substitute for each match.
+    pub replace: Option<String>,
+    /// Write replacements back to the files rather than to stdout.
+    pub in_place: bool,
+    pub backup_suffix: Option<String>,
+    /// Match one field of each CSV or JSON Lines record, not whole lines.
+    pub field: Option<Field>,
+    /// Match against whole inputs, so matches can span lines.
+    pub multiline: bool,
+    pub invert_match: bool,
+    pub line_number: bool,
+    pub count: bool,
+    /// With `count`, count individual matches instead of lines.
+    pub count_matches: bool,
+    pub files_with_matches: bool,
+    /// Print nothing; just stop at the first match.
+    pub quiet: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    /// How many files are searched at once; output order is unaffected.
+    pub jobs: usize,
+    pub color: ColorChoice,
+    /// Emit machine-readable JSON Lines instead of grep-style text.
+    pub json: bool,
+    /// Print a summary of the whole run at the end.
+    pub stats: bool,
+    /// Transparently decompress gzip, zstd and bzip2 input.
+    pub search_zip: bool,
+    /// Wait for more lines to be appended instead of stopping at EOF.
+    pub follow: bool,
+    /// Decode input from this instead of UTF-8, unless it has a BOM.
+    pub encoding: Option<&'static Encoding>,
+    /// Print matching lines of binary files instead of skipping them
+    /// or just saying that they match.
+    pub text: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    MissingQuery,
+    MissingFilePath,
+    UnknownFlag(String),
+    /// The flag needs a value but none followed it.
+    MissingValue(String),
+    InvalidValue {
+        flag: String,
+        value: String,
+    },
+    InvalidPattern(regex::Error),
+    InvalidQuery(QueryError),
+    /// A pattern file given with `-f` couldn't be read.
+    PatternFile {
+        path: String,
+        source: io::Error,
+    },
+    /// Two flags that can't be used together.
+    Conflict {
+        flag: String,
+        other: String,
+    },
+    /// A flag that only makes sense together with another one.
+    Requires {
+        flag: String,
+        other: String,
+    },
+    /// A config file exists but isn't valid TOML, or couldn't be read.
+    SettingsFile {
+        path: PathBuf,
+        message: String,
+    },
+    /// A config file or environment variable sets a flag that doesn't
+    /// exist, can't be set that way, or to a value it doesn't take.
+    InvalidSetting {
+        name: String,
+        value: String,
+        source: String,
+    },
+    /// `--debug-config` was given; displays as the report of settings.
+    DebugConfig(String),
+    /// `-h`/`--help` was given; displays as the usage text.
+    Help,
+    /// `-V`/`--version` was given; displays as the version line.
+    Version,
+}
+
+impl ConfigError {
+    /// Help and version requests aren't failures, they just stop parsing.
+    pub fn is_informational(&self) -> bool {
+        matches!(
+            self,
+            ConfigError::DebugConfig(_) | ConfigError::Help | ConfigError::Version
+        )
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::MissingQuery => write!(f, "Didn't get a query string."),
+            ConfigError::MissingFilePath => write!(f, "Didn't get a file path."),
+            ConfigError::UnknownFlag(flag) => write!(f, "Unknown flag '{}'.", flag),
+            ConfigError::MissingValue(flag) => write!(f, "Flag '{}' needs a value.", flag),
+            ConfigError::InvalidValue { flag, value } => {
+                write!(f, "Invalid value '{}' for flag '{}'.", value, flag)
+            }
+            ConfigError::InvalidPattern(err) => write!(f, "Invalid regex pattern: {}", err),
+            ConfigError::InvalidQuery(err) => write!(f, "Invalid query: {}", err),
+            ConfigError::PatternFile { path, source } => {
+                write!(f, "Couldn't read patterns from '{}': {}", path, source)
+            }
+            ConfigError::Conflict { flag, other } => {
+                write!(f, "Flag '{}' can't be combined with '{}'.", flag, other)
+            }
+            ConfigError::Requires { flag, other } => {
+                write!(f, "Flag '{}' can only be used with '{}'.", flag, other)
+            }
+            ConfigError::SettingsFile { path, message } => {
+                write!(
+                    f,
+                    "Couldn't read settings from '{}': {}",
+                    path.display(),
+                    message
+                )
+            }
+            ConfigError::InvalidSetting {
+                name,
+                value,
+                source,
+            } => write!(f, "Invalid setting '{} = {}' from {}.", name, value, source),
+            ConfigError::DebugConfig(report) => write!(f, "{}", report.trim_end()),
+            ConfigError::Help => write!(f, "{}", USAGE),
+            ConfigError::Version => write!(f, "minigrep {}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::InvalidPattern(err) => Some(err),
+            ConfigError::InvalidQuery(err) => Some(err),
+            ConfigError::PatternFile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Opt {
+    Regex,
+    Regexp,
+    PatternFile,
+    FixedStrings,
+    Expr,
+    IgnoreCase,
+    CaseSensitive,
+    Turkic,
+    Normalize,
+    Fuzzy,
+    Replace,
+    InPlace,
+    Backup,
+    CsvColumn,
+    JsonPath,
+    Multiline,
+    InvertMatch,
+    LineNumber,
+    Count,
+    CountMatches,
+    FilesWithMatches,
+    Quiet,
+    AfterContext,
+    BeforeContext,
+    Context,
+    Jobs,
+    Color,
+    Stats,
+    Json,
+    SearchZip,
+    Follow,
+    Encoding,
+    Text,
+    DebugConfig,
+    NoConfig,
+    Help,
+    Version,
+}
+
+// Every flag with its short and long spelling.
+const OPTS: &[(Opt, Option<char>, &str)] = &[
+    (Opt::Regex, None, "regex"),
+    (Opt::Regexp, Some('e'), "regexp"),
+    (Opt::PatternFile, Some('f'), "file"),
+    (Opt::FixedStrings, Some('F'), "fixed-strings"),
+    (Opt::Expr, None, "expr"),
+    (Opt::IgnoreCase, Some('i'), "ignore-case"),
+    (Opt::CaseSensitive, Some('s'), "case-sensitive"),
+    (Opt::Turkic, None, "turkic"),
+    (Opt::Normalize, None, "normalize"),
+    (Opt::Fuzzy, None, "fuzzy"),
+    (Opt::Replace, Some('r'), "replace"),
+    (Opt::InPlace, None, "in-place"),
+    (Opt::Backup, None, "backup"),
+    (Opt::CsvColumn, None, "csv-column"),
+    (Opt::JsonPath, None, "json-path"),
+    (Opt::Multiline, Some('U'), "multiline"),
+    (Opt::InvertMatch, Some('v'), "invert-match"),
+    (Opt::LineNumber, Some('n'), "line-number"),
+    (Opt::Count, Some('c'), "count"),
+    (Opt::CountMatches, None, "count-matches"),
+    (Opt::FilesWithMatches, Some('l'), "files-with-matches"),
+    (Opt::Quiet, Some('q'), "quiet"),
+    (Opt::AfterContext, Some('A'), "after-context"),
+    (Opt::BeforeContext, Some('B'), "before-context"),
+    (Opt::Context, Some('C'), "context"),
+    (Opt::Jobs, Some('j'), "jobs"),
+    (Opt::Color, None, "color"),
+    (Opt::Stats, None, "stats"),
+    (Opt::Json, None, "json"),
+    (Opt::SearchZip, Some('z'), "search-zip"),
+    (Opt::Follow, None, "follow"),
+    (Opt::Encoding, None, "encoding"),
+    (Opt::Text, Some('a'), "text"),
+    (Opt::DebugConfig, None, "debug-config"),
+    (Opt::NoConfig, None, "no-config"),
+    (Opt::Help, Some('h'), "help"),
+    (Opt::Version, Some('V'), "version"),
+];
+
+impl Opt {
+    fn from_short(flag: char) -> Option<Opt> {
+        OPTS.iter()
+            .find(|(_, short, _)| *short == Some(flag))
+            .map(|(opt, _, _)| *opt)
+    }
+
+    fn from_long(name: &str) -> Option<Opt> {
+        OPTS.iter()
+            .find(|(_, _, long)| *long == name)
+            .map(|(opt, _, _)| *opt)
+    }
+
+    fn long(self) -> &'static str {
+        OPTS.iter()
+            .find(|(opt, _, _)| *opt == self)
+            .map(|(_, _, long)| *long)
+            .expect("every flag has a long name")
+    }
+
+    // Only flags that don't change what is searched for can be defaulted
+    // by config files and environment variables.
+    fn configurable(self) -> bool {
+        matches!(
+            self,
+            Opt::IgnoreCase
+                | Opt::CaseSensitive
+                | Opt::Turkic
+                | Opt::Normalize
+                | Opt::LineNumber
+                | Opt::AfterContext
+                | Opt::BeforeContext
+                | Opt::Context
+                | Opt::Jobs
+                | Opt::Color
+                | Opt::Stats
+                | Opt::SearchZip
+                | Opt::Encoding
+                | Opt::Text
+        )
+    }
+
+    fn takes_value(self) -> bool {
+        matches!(
+            self,
+            Opt::Regexp
+                | Opt::PatternFile
+                | Opt::Expr
+                | Opt::AfterContext
+                | Opt::BeforeContext
+                | Opt::Context
+                | Opt::Jobs
+                | Opt::Color
+                | Opt::Encoding
+                | Opt::Fuzzy
+                | Opt::Replace
+                | Opt::Backup
+                | Opt::CsvColumn
+                | Opt::JsonPath
+                | Opt::Normalize
+        )
+    }
+}
+
+// Settings that are only resolved once every flag has been seen.
+#[derive(Default)]
+struct Pending {
+    use_regex: bool,
+    fixed_strings: bool,
+    // Patterns from `-e`, which are regexes unless `-F` is given.
+    regexps: Vec<String>,
+    // Patterns from `-f` files, which are literals unless told otherwise.
+    file_patterns: Vec<String>,
+    pattern_file_given: bool,
+    before_context: Option<usize>,
+    after_context: Option<usize>,
+    context: Option<usize>,
+    debug_config: bool,
+}
+
+impl Pending {
+    fn context_given(&self) -> bool {
+        self.before_context.is_some() || self.after_context.is_some() || self.context.is_some()
+    }
+}
+
+impl Config {
+    /// Parses the command line on top of the settings from config files
+    /// and the environment.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        let args: Vec<String> = args.collect();
+        // Only the command line can say to ignore everything else.
+        let no_config = args
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .any(|arg| arg == "--no-config");
+        if no_config {
+            return Config::build_from(args.into_iter(), &Files::default(), Vec::new());
+        }
+
+        let files = Files::locate();
+        let mut layered = files.read().map_err(|err| ConfigError::SettingsFile {
+            path: err.path,
+            message: err.message,
+        })?;
+        let vars = env::vars_os()
+            .filter_map(|(var, value)| Some((var.into_string().ok()?, value.into_string().ok()?)));
+        layered.extend(settings::from_env(vars, |name| {
+            Opt::from_long(name).map(|opt| !opt.takes_value())
+        }));
+        Config::build_from(args.into_iter(), &files, layered)
+    }
+
+    /// Parses the command line alone, as with `--no-config`, so that tests
+    /// don't depend on the settings of whoever runs them.
+    #[cfg(test)]
+    pub(crate) fn from_args(args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        Config::build_from(args, &Files::default(), Vec::new())
+    }
+
+    // `layered` holds the settings from below the command line, lowest
+    // precedence first.
+    fn build_from(
+        mut args: impl Iterator<Item = String>,
+        files: &Files,
+        layered: Vec<Setting>,
+    ) -> Result<Config, ConfigError> {
+        args.next();
+
+        let mut config = Config {
+            query: String::new(),
+            paths: Vec::new(),
+            ignore_case: false,
+            turkic: false,
+            normalization: None,
+            patterns: Vec::new(),
+            expr: None,
+            regex: None,
+            multi_pattern: None,
+            fuzzy: None,
+            replace: None,
+            in_place: false,
+            backup_suffix: None,
+            field: None,
+            multiline: false,
+            invert_match: false,
+            line_number: false,
+            count: false,
+            count_matches: false,
+            files_with_matches: false,
+            quiet: false,
+            before_context: 0,
+            after_context: 0,
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            color: ColorChoice::Auto,
+            json: false,
+            stats: false,
+            search_zip: false,
+            follow: false,
+            encoding: None,
+            text: false,
+        };
+        // Kept apart so that context flags on the command line replace
+        // the configured ones instead of mixing with them.
+        let mut layer_pending = Pending::default();
+        for setting in &layered {
+            config.apply_setting(setting, &mut layer_pending)?;
+        }
+        let mut applied = layered;
+
+        let mut pending = Pending::default();
+        let mut positional = Vec::new();
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                positional.extend(args.by_ref());
+                break;
+            }
+
+            if let Some(long) = arg.strip_prefix("--") {
+                // Values may be attached (`--context=2`) or follow (`--context 2`).
+                let (name, attached) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let opt = match Opt::from_long(name) {
+                    Some(opt) => opt,
+                    None => return Err(ConfigError::UnknownFlag(arg)),
+                };
+                let value = if opt.takes_value() {
+                    match attached.or_else(|| args.next()) {
+                        Some(value) => Some(value),
+                        None => return Err(ConfigError::MissingValue(arg)),
+                    }
+                } else if attached.is_some() {
+                    return Err(ConfigError::UnknownFlag(arg));
+                } else {
+                    None
+                };
+                note(&mut applied, opt, &value);
+                config.apply(opt, value, &arg, &mut pending)?;
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                // Short flags may be bundled (`-in`), and the last one may
+                // carry its value in the same argument (`-nC2`).
+                for (i, flag) in arg.char_indices().skip(1) {
+                    let opt = match Opt::from_short(flag) {
+                        Some(opt) => opt,
+                        None => return Err(ConfigError::UnknownFlag(arg)),
+                    };
+                    if !opt.takes_value() {
+                        note(&mut applied, opt, &None);
+                        config.apply(opt, None, &arg, &mut pending)?;
+                        continue;
+                    }
+
+                    let rest = &arg[i + flag.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        match args.next() {
+                            Some(value) => value,
+                            None => return Err(ConfigError::MissingValue(arg)),
+                        }
+                    } else {
+                        rest.to_string()
+                    };
+                    let value = Some(value);
+                    note(&mut applied, opt, &value);
+                    config.apply(opt, value, &arg, &mut pending)?;
+                    break;
+                }
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        if pending.debug_config {
+            return Err(ConfigError::DebugConfig(report(files, &applied)));
+        }
+
+        let mut positional = positional.into_iter();
+        let patterns_given =
+            !pending.regexps.is_empty() || pending.pattern_file_given || config.expr.is_some();
+
+        if patterns_given {
+            if config.expr.is_some() && !pending.regexps.is_empty() {
+                return Err(conflict("--expr", "-e"));
+            }
+            if config.expr.is_some() && pending.pattern_file_given {
+                return Err(conflict("--expr", "-f"));
+            }
+            config.patterns = pending.regexps.clone();
+            config.patterns.append(&mut pending.file_patterns);
+        } else {
+            match positional.next() {
+                Some(arg) => config.patterns.push(arg),
+                None => return Err(ConfigError::MissingQuery),
+            }
+        }
+
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            return Err(ConfigError::MissingFilePath);
+        }
+
+        // Like grep, `-e` patterns are regexes unless `-F` says otherwise.
+        let use_regex =
+            !pending.fixed_strings && (pending.use_regex || !pending.regexps.is_empty());
+        let options = QueryOptions {
+            regex: use_regex,
+            ignore_case: config.ignore_case,
+            multi_line: config.multiline,
+            fold: config.fold_options(),
+        };
+
+        if let Some(expr) = &config.expr {
+            config.query = expr.clone();
+            let query = Query::parse(expr, options).map_err(ConfigError::InvalidQuery)?;
+            config.multi_pattern = Some(query);
+        } else if config.patterns.len() > 1 {
+            // Newline-separated, the way grep accepts a list of patterns.
+            config.query = config.patterns.join("\n");
+            let query = Query::any(&config.patterns, options).map_err(ConfigError::InvalidQuery)?;
+            config.multi_pattern = Some(query);
+        } else {
+            config.query = match config.patterns.first() {
+                Some(pattern) => pattern.clone(),
+                // Only an empty `-f` file gets here.
+                None => return Err(ConfigError::MissingQuery),
+            };
+            if use_regex {
+                let regex = RegexBuilder::new(&config.query)
+                    .case_insensitive(config.ignore_case)
+                    .multi_line(config.multiline)
+                    .build()
+                    .map_err(ConfigError::InvalidPattern)?;
+                config.regex = Some(regex);
+            }
+        }
+
+        // As in grep, an explicit -A/-B wins over -C regardless of order.
+        let context = if pending.context_given() {
+            &pending
+        } else {
+            &layer_pending
+        };
+        config.before_context = context.before_context.or(context.context).unwrap_or(0);
+        config.after_context = context.after_context.or(context.context).unwrap_or(0);
+
+        if config.fuzzy.is_some() {
+            // Fuzzy matching only makes sense for a single literal query,
+            // and ranked output has no room for context lines, nor does
+            // it substitute anything.
+            if config.expr.is_some() {
+                return Err(conflict("--fuzzy", "--expr"));
+            }
+            if !pending.regexps.is_empty() {
+                return Err(conflict("--fuzzy", "-e"));
+            }
+            if pending.pattern_file_given {
+                return Err(conflict("--fuzzy", "-f"));
+            }
+            if use_regex {
+                return Err(conflict("--fuzzy", "--regex"));
+            }
+            if config.before_context > 0 || config.after_context > 0 {
+                return Err(conflict("--fuzzy", "-A/-B/-C"));
+            }
+            if config.replace.is_some() {
+                return Err(conflict("--fuzzy", "--replace"));
+            }
+        }
+
+        if config.follow {
+            // Output is only ever flushed per file, and a followed file
+            // never ends, so there can only be one.
+            if config.paths.len() > 1 {
+                return Err(conflict("--follow", "more than one PATH"));
+            }
+            if config.paths[0] == crate::STDIN_PATH {
+                return Err(conflict("--follow", crate::STDIN_PATH));
+            }
+            // These only print anything once the whole input is read.
+            if config.count {
+                return Err(conflict("--follow", "-c"));
+            }
+            if config.fuzzy.is_some() {
+                return Err(conflict("--follow", "--fuzzy"));
+            }
+            if config.in_place {
+                return Err(conflict("--follow", "--in-place"));
+            }
+            if config.search_zip {
+                return Err(conflict("--follow", "-z"));
+            }
+        }
+
+        if config.field.is_some() {
+            // Records aren't lines, so none of these apply to them.
+            let flag = match config.field {
+                Some(Field::CsvColumn(_)) => "--csv-column",
+                _ => "--json-path",
+            };
+            if config.before_context > 0 || config.after_context > 0 {
+                return Err(conflict(flag, "-A/-B/-C"));
+            }
+            if config.fuzzy.is_some() {
+                return Err(conflict(flag, "--fuzzy"));
+            }
+            if config.in_place {
+                return Err(conflict(flag, "--in-place"));
+            }
+        }
+
+        if config.multiline {
+            // Matches are whole runs of lines, so there's nothing to invert
+            // or put context around, and the rest work line by line.
+            if config.invert_match {
+                return Err(conflict("-U", "-v"));
+            }
+            if config.before_context > 0 || config.after_context > 0 {
+                return Err(conflict("-U", "-A/-B/-C"));
+            }
+            if config.expr.is_some() {
+                return Err(conflict("-U", "--expr"));
+            }
+            if config.fuzzy.is_some() {
+                return Err(conflict("-U", "--fuzzy"));
+            }
+            if config.field.is_some() {
+                return Err(conflict("-U", "--csv-column/--json-path"));
+            }
+            if config.follow {
+                return Err(conflict("-U", "--follow"));
+            }
+            if config.in_place {
+                return Err(conflict("-U", "--in-place"));
+            }
+        }
+
+        if config.backup_suffix.is_some() && !config.in_place {
+            return Err(requires("--backup", "--in-place"));
+        }
+        if config.in_place {
+            if config.replace.is_none() {
+                return Err(requires("--in-place", "--replace"));
+            }
+            // Edits are made to the bytes on disk, line by line.
+            if config.invert_match {
+                return Err(conflict("--in-place", "-v"));
+            }
+            if config.search_zip {
+                return Err(conflict("--in-place", "-z"));
+            }
+            if config.encoding.is_some() {
+                return Err(conflict("--in-place", "--encoding"));
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn pattern(&self) -> Pattern {
+        if let Some(max_distance) = self.fuzzy {
+            return Pattern::Fuzzy(FuzzyQuery::new(&self.query, max_distance, self.ignore_case));
+        }
+
+        if let Some(query) = &self.multi_pattern {
+            return Pattern::Query(query.clone());
+        }
+
+        if let Some(regex) = &self.regex {
+            return Pattern::Regex(regex.clone());
+        }
+
+        let options = self.fold_options();
+        if options == FoldOptions::default() {
+            return Pattern::literal(&self.query);
+        }
+        Pattern::Folded(FoldedQuery::new(&self.query, options))
+    }
+
+    fn fold_options(&self) -> FoldOptions {
+        FoldOptions {
+            case_fold: self.ignore_case,
+            turkic: self.turkic,
+            normalization: self.normalization,
+        }
+    }
+
+    fn apply(
+        &mut self,
+        opt: Opt,
+        value: Option<String>,
+        arg: &str,
+        pending: &mut Pending,
+    ) -> Result<(), ConfigError> {
+        let number = || -> Result<usize, ConfigError> {
+            let value = value.clone().unwrap_or_default();
+            value.parse().map_err(|_| ConfigError::InvalidValue {
+                flag: arg.to_string(),
+                value,
+            })
+        };
+
+        match opt {
+            Opt::Regex => pending.use_regex = true,
+            Opt::Regexp => pending.regexps.extend(value),
+            Opt::PatternFile => {
+                let path = value.unwrap_or_default();
+                let contents = fs::read_to_string(&path)
+                    .map_err(|source| ConfigError::PatternFile { path, source })?;
+                // Blank lines would match everything, so they're skipped.
+                let patterns = contents.lines().filter(|line| !line.is_empty());
+                pending.file_patterns.extend(patterns.map(String::from));
+                pending.pattern_file_given = true;
+            }
+            Opt::FixedStrings => pending.fixed_strings = true,
+            Opt::Expr => self.expr = value,
+            Opt::IgnoreCase => self.ignore_case = true,
+            Opt::CaseSensitive => self.ignore_case = false,
+            Opt::Turkic => self.turkic = true,
+            Opt::Normalize => {
+                self.normalization = match value.as_deref() {
+                    Some("nfc") => Some(Normalization::Nfc),
+                    Some("nfd") => Some(Normalization::Nfd),
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            flag: arg.to_string(),
+                            value: value.unwrap_or_default(),
+                        })
+                    }
+                }
+            }
+            Opt::Fuzzy => self.fuzzy = Some(number()?),
+            Opt::Replace => self.replace = value,
+            Opt::InPlace => self.in_place = true,
+            Opt::Backup => self.backup_suffix = value,
+            // Given twice, the last one wins, as with other values.
+            Opt::CsvColumn if matches!(self.field, Some(Field::JsonPath(_))) => {
+                return Err(conflict("--csv-column", "--json-path"))
+            }
+            Opt::JsonPath if matches!(self.field, Some(Field::CsvColumn(_))) => {
+                return Err(conflict("--json-path", "--csv-column"))
+            }
+            Opt::CsvColumn => self.field = value.map(Field::CsvColumn),
+            Opt::JsonPath => {
+                let path = value.unwrap_or_default();
+                match Field::json_path(&path) {
+                    Some(field) => self.field = Some(field),
+                    None => {
+                        return Err(ConfigError::InvalidValue {
+                            flag: arg.to_string(),
+                            value: path,
+                        })
+                    }
+                }
+            }
+            Opt::Multiline => self.multiline = true,
+            Opt::InvertMatch => self.invert_match = true,
+            Opt::LineNumber => self.line_number = true,
+            Opt::Count => self.count = true,
+            Opt::CountMatches => {
+                self.count = true;
+                self.count_matches = true;
+            }
+            Opt::FilesWithMatches => self.files_with_matches = true,
+            Opt::Quiet => self.quiet = true,
+            Opt::AfterContext => pending.after_context = Some(number()?),
+            Opt::BeforeContext => pending.before_context = Some(number()?),
+            Opt::Context => pending.context = Some(number()?),
+            Opt::Jobs => {
+                self.jobs = number()?;
+                if self.jobs == 0 {
+                    return Err(ConfigError::InvalidValue {
+                        flag: arg.to_string(),
+                        value: "0".to_string(),
+                    });
+                }
+            }
+            Opt::Color => {
+                self.color = match value.as_deref() {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            flag: arg.to_string(),
+                            value: value.unwrap_or_default(),
+                        })
+                    }
+                }
+            }
+            Opt::Stats => self.stats = true,
+            Opt::Json => self.json = true,
+            Opt::SearchZip => self.search_zip = true,
+            Opt::Follow => self.follow = true,
+            Opt::Encoding => {
+                let label = value.unwrap_or_default();
+                match encoding::for_label(&label) {
+                    Some(encoding) => self.encoding = Some(encoding),
+                    None => {
+                        return Err(ConfigError::InvalidValue {
+                            flag: arg.to_string(),
+                            value: label,
+                        })
+                    }
+                }
+            }
+            Opt::Text => self.text = true,
+            Opt::DebugConfig => pending.debug_config = true,
+            // Already seen by `build`.
+            Opt::NoConfig => {}
+            Opt::Help => return Err(ConfigError::Help),
+            Opt::Version => return Err(ConfigError::Version),
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    fn apply_setting(
+        &mut self,
+        setting: &Setting,
+        pending: &mut Pending,
+    ) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidSetting {
+            name: setting.name.clone(),
+            value: setting.value.to_string(),
+            source: setting.source.to_string(),
+        };
+        let opt = Opt::from_long(&setting.name)
+            .filter(|opt| opt.configurable())
+            .ok_or_else(invalid)?;
+        let flag = format!("--{}", setting.name);
+
+        match (&setting.value, opt.takes_value()) {
+            (Value::Switch(true), false) => self.apply(opt, None, &flag, pending),
+            (Value::Switch(false), false) => {
+                self.switch_off(opt);
+                Ok(())
+            }
+            (Value::Text(text), true) => self
+                .apply(opt, Some(text.clone()), &flag, pending)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+
+    // What `name = false` means: back to the default. There is no
+    // opposite of `case-sensitive`, so that one does nothing.
+    fn switch_off(&mut self, opt: Opt) {
+        match opt {
+            Opt::IgnoreCase => self.ignore_case = false,
+            Opt::Turkic => self.turkic = false,
+            Opt::LineNumber => self.line_number = false,
+            Opt::Stats => self.stats = false,
+            Opt::SearchZip => self.search_zip = false,
+            Opt::Text => self.text = false,
+            _ => {}
+        }
+    }
+}
+
+// Records a flag from the command line for `--debug-config`.
+fn note(applied: &mut Vec<Setting>, opt: Opt, value: &Option<String>) {
+    if !opt.configurable() {
+        return;
+    }
+    let value = match value {
+        Some(text) => Value::Text(text.clone()),
+        None => Value::Switch(true),
+    };
+    applied.push(Setting {
+        name: opt.long().to_string(),
+        value,
+        source: Source::CommandLine,
+    });
+}
+
+// What `--debug-config` prints: the files looked at, then each setting
+// in order of precedence, as TOML.
+fn report(files: &Files, applied: &[Setting]) -> String {
+    let mut report = String::new();
+
+    for (kind, path) in [("user", &files.user), ("project", &files.project)] {
+        let line = match path {
+            Some(path) if path.is_file() => format!("# {} config: {}\n", kind, path.display()),
+            Some(path) => format!("# {} config: {} (not found)\n", kind, path.display()),
+            None => format!("# {} config: none\n", kind),
+        };
+        report.push_str(&line);
+    }
+    if applied.is_empty() {
+        report.push_str("# every setting is at its default\n");
+    }
+
+    for (i, setting) in applied.iter().enumerate() {
+        let overridden = applied[i + 1..]
+            .iter()
+            .any(|later| later.name == setting.name);
+        report.push_str(&format!(
+            "{} = {}  # from {}{}\n",
+            setting.name,
+            setting.value,
+            setting.source,
+            if overridden { ", overridden" } else { "" }
+        ));
+    }
+    report
+}
+
+fn conflict(flag: &str, other: &str) -> ConfigError {
+    ConfigError::Conflict {
+        flag: flag.to_string(),
+        other: other.to_string(),
+    }
+}
+
+fn requires(flag: &str, other: &str) -> ConfigError {
+    ConfigError::Requires {
+        flag: flag.to_string(),
+        other: other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(args: &[&str]) -> Result<Config, ConfigError> {
+        let args = std::iter::once("minigrep").chain(args.iter().copied());
+        Config::from_args(args.map(String::from))
+    }
+
+    #[test]
+    fn bundled_short_flags() {
+        let config = build(&["-nvc", "duct", "a.txt", "b.txt"]).unwrap();
+
+        assert!(config.line_number && config.invert_match && config.count);
+        assert!(!config.files_with_matches);
+        assert_eq!("duct", config.query);
+        assert_eq!(vec!["a.txt", "b.txt"], config.paths);
+    }
+
+    #[test]
+    fn count_matches_implies_count() {
+        let config = build(&["--count-matches", "--stats", "duct", "a.txt"]).unwrap();
+        assert!(config.count && config.count_matches && config.stats);
+
+        let config = build(&["-c", "duct", "a.txt"]).unwrap();
+        assert!(!config.count_matches && !config.stats);
+    }
+
+    #[test]
+    fn multiline() {
+        let config = build(&["-U", "--regex", r"^fn .*\n\s+\}", "a.rs"]).unwrap();
+        assert!(config.multiline);
+        assert!(config.regex.unwrap().is_match("x\nfn main() {\n    }"));
+
+        assert!(matches!(
+            build(&["-U", "-v", "a\nb", "a.txt"]),
+            Err(ConfigError::Conflict { .. })
+        ));
+    }
+
+    fn layered(settings: &[(&str, Value, Source)], args: &[&str]) -> Result<Config, ConfigError> {
+        let layered = settings
+            .iter()
+            .map(|(name, value, source)| Setting {
+                name: name.to_string(),
+                value: value.clone(),
+                source: source.clone(),
+            })
+            .collect();
+        let args = std::iter::once("minigrep").chain(args.iter().copied());
+        Config::build_from(args.map(String::from), &Files::default(), layered)
+    }
+
+    fn text(value: &str) -> Value {
+        Value::Text(value.to_string())
+    }
+
+    #[test]
+    fn settings_by_precedence() {
+        let user = Source::UserFile(PathBuf::from("config.toml"));
+        let project = Source::ProjectFile(PathBuf::from(".minigreprc"));
+        let env = Source::Env("MINIGREP_LINE_NUMBER".to_string());
+        let settings = [
+            ("line-number", Value::Switch(true), user.clone()),
+            ("context", text("2"), user.clone()),
+            ("color", text("never"), project.clone()),
+            ("line-number", Value::Switch(false), env.clone()),
+            ("jobs", text("3"), project),
+        ];
+
+        let config = layered(&settings, &["duct", "a.txt"]).unwrap();
+        assert!(!config.line_number);
+        assert_eq!(ColorChoice::Never, config.color);
+        assert_eq!(
+            (2, 2, 3),
+            (config.before_context, config.after_context, config.jobs)
+        );
+
+        // Context from the command line replaces the configured context.
+        let config = layered(&settings, &["-A1", "-j5", "duct", "a.txt"]).unwrap();
+        assert_eq!(
+            (0, 1, 5),
+            (config.before_context, config.after_context, config.jobs)
+        );
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let env = Source::Env("MINIGREP_X".to_string());
+        for (name, value) in [
+            ("no-such-flag", Value::Switch(true)),
+            ("invert-match", Value::Switch(true)),
+            ("context", text("lots")),
+            ("line-number", text("yes")),
+        ] {
+            let settings = [(name, value, env.clone())];
+            match layered(&settings, &["duct", "a.txt"]) {
+                Err(ConfigError::InvalidSetting { source, .. }) => {
+                    assert_eq!("$MINIGREP_X", source)
+                }
+                other => panic!("{} should be invalid, got {:?}", name, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn debug_config_report() {
+        let settings = [
+            (
+                "color",
+                text("never"),
+                Source::UserFile(PathBuf::from("config.toml")),
+            ),
+            (
+                "ignore-case",
+                Value::Switch(true),
+                Source::Env("IGNORE_CASE".to_string()),
+            ),
+        ];
+        let err = layered(&settings, &["--debug-config", "--color", "always"])
+            .err()
+            .unwrap();
+        assert!(err.is_informational());
+
+        let report = err.to_string();
+        assert!(report.contains("color = \"never\"  # from config.toml, overridden\n"));
+        assert!(report.contains("ignore-case = true  # from $IGNORE_CASE\n"));
+        assert!(report.ends_with("color = \"always\"  # from command line"));
+    }
+
+    #[test]
+    fn flags_override_env_var() {
+        let config = build(&["--case-sensitive", "duct", "a.txt"]).unwrap();
+        assert!(!config.ignore_case);
+
+        let config = build(&["duct", "a.txt", "-i"]).unwrap();
+        assert!(config.ignore_case);
+    }
+
+    #[test]
+    fn double_dash_ends_flags() {
+        let config = build(&["-l", "--", "-v", "-n"]).unwrap();
+
+        assert!(config.files_with_matches);
+        assert!(!config.invert_match);
+        assert_eq!("-v", config.query);
+        assert_eq!(vec!["-n"], config.paths);
+    }
+
+    #[test]
+    fn unknown_flag_names_the_argument() {
+        match build(&["-nx", "duct", "a.txt"]) {
+            Err(ConfigError::UnknownFlag(arg)) => assert_eq!("-nx", arg),
+            _ => panic!("expected an unknown flag error"),
+        }
+        match build(&["--colour", "duct", "a.txt"]) {
+            Err(ConfigError::UnknownFlag(arg)) => assert_eq!("--colour", arg),
+            _ => panic!("expected an unknown flag error"),
+        }
+    }
+
+    #[test]
+    fn help_and_version_stop_parsing() {
+        let err = build(&["duct", "--help"]).err().unwrap();
+        assert!(err.is_informational());
+        assert!(err.to_string().starts_with("Usage: minigrep"));
+
+        let err = build(&["-V"]).err().unwrap();
+        assert!(err.to_string().starts_with("minigrep "));
+    }
+
+    #[test]
+    fn missing_positionals() {
+        assert!(matches!(build(&["-n"]), Err(ConfigError::MissingQuery)));
+        assert!(matches!(
+            build(&["duct"]),
+            Err(ConfigError::MissingFilePath)
+        ));
+    }
+
+    #[test]
+    fn context_values() {
+        let config = build(&["-C", "3", "-A1", "duct", "a.txt"]).unwrap();
+        assert_eq!((3, 1), (config.before_context, config.after_context));
+
+        let config = build(&["-nB2", "--after-context=4", "duct", "a.txt"]).unwrap();
+        assert!(config.line_number);
+        assert_eq!((2, 4), (config.before_context, config.after_context));
+    }
+
+    #[test]
+    fn bad_context_values() {
+        match build(&["-A", "many", "duct", "a.txt"]) {
+            Err(ConfigError::InvalidValue { flag, value }) => {
+                assert_eq!(("-A", "many"), (flag.as_str(), value.as_str()))
+            }
+            _ => panic!("expected an invalid value error"),
+        }
+        assert!(matches!(
+            build(&["duct", "a.txt", "--context"]),
+            Err(ConfigError::MissingValue(_))
+        ));
+    }
+
+    #[test]
+    fn jobs() {
+        let config = build(&["-j", "3", "duct", "a.txt"]).unwrap();
+        assert_eq!(3, config.jobs);
+
+        assert!(matches!(
+            build(&["-j0", "duct", "a.txt"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn color_choice() {
+        let config = build(&["duct", "a.txt"]).unwrap();
+        assert_eq!(ColorChoice::Auto, config.color);
+
+        let config = build(&["--color=always", "duct", "a.txt"]).unwrap();
+        assert_eq!(ColorChoice::Always, config.color);
+
+        let config = build(&["--color", "never", "duct", "a.txt"]).unwrap();
+        assert_eq!(ColorChoice::Never, config.color);
+
+        assert!(matches!(
+            build(&["--color=sometimes", "duct", "a.txt"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn folding_options() {
+        let config = build(&["-i", "--turkic", "--normalize=nfd", "duct", "a.txt"]).unwrap();
+        let expected = FoldOptions {
+            case_fold: true,
+            turkic: true,
+            normalization: Some(Normalization::Nfd),
+        };
+        match config.pattern() {
+            Pattern::Folded(query) => assert_eq!(expected, query.options()),
+            pattern => panic!("expected a folded pattern, got {:?}", pattern),
+        }
+
+        assert!(matches!(
+            build(&["--normalize", "nfkc", "duct", "a.txt"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn repeated_regexps() {
+        let config = build(&["-e", r"ERROR \d+", "-e", "^WARN", "app.log"]).unwrap();
+
+        assert_eq!(vec![r"ERROR \d+", "^WARN"], config.patterns);
+        assert_eq!(vec!["app.log"], config.paths);
+        let pattern = config.pattern();
+        assert!(pattern.is_match("ERROR 42"));
+        assert!(pattern.is_match("WARN disk"));
+        assert!(!pattern.is_match("INFO ok"));
+    }
+
+    #[test]
+    fn single_regexp_keeps_query_and_regex() {
+        let config = build(&["-e", "a.c", "app.log"]).unwrap();
+
+        assert_eq!("a.c", config.query);
+        assert!(config.regex.is_some());
+        assert!(config.multi_pattern.is_none());
+    }
+
+    #[test]
+    fn fixed_strings_from_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let patterns = dir.path().join("patterns.txt");
+        fs::write(&patterns, "a.c\n\n(x\n").unwrap();
+        let patterns = patterns.to_str().unwrap();
+
+        let config = build(&["-f", patterns, "app.log"]).unwrap();
+        assert_eq!(vec!["a.c", "(x"], config.patterns);
+        let pattern = config.pattern();
+        assert!(pattern.is_match("1 (x 2"));
+        assert!(!pattern.is_match("abc"));
+
+        // -F turns -e patterns into literals too.
+        let config = build(&["-F", "-e", "a.c", "-f", patterns, "app.log"]).unwrap();
+        assert!(!config.pattern().is_match("abc"));
+
+        assert!(matches!(
+            build(&["-f", "does/not/exist", "app.log"]),
+            Err(ConfigError::PatternFile { .. })
+        ));
+    }
+
+    #[test]
+    fn boolean_expressions() {
+        let config = build(&["--expr", "foo AND NOT bar", "app.log"]).unwrap();
+        let pattern = config.pattern();
+        assert!(pattern.is_match("foo"));
+        assert!(!pattern.is_match("foo bar"));
+
+        assert!(matches!(
+            build(&["--expr", "foo AND", "app.log"]),
+            Err(ConfigError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            build(&["--expr", "foo", "-e", "bar", "app.log"]),
+            Err(ConfigError::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn encoding_labels() {
+        let config = build(&["duct", "a.txt"]).unwrap();
+        assert_eq!(None, config.encoding);
+
+        let config = build(&["--encoding", "Latin1", "duct", "a.txt"]).unwrap();
+        assert_eq!(Some(encoding_rs::WINDOWS_1252), config.encoding);
+
+        let config = build(&["--encoding=utf-16le", "duct", "a.txt"]).unwrap();
+        assert_eq!(Some(encoding_rs::UTF_16LE), config.encoding);
+
+        assert!(matches!(
+            build(&["--encoding", "klingon", "duct", "a.txt"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn fuzzy() {
+        let config = build(&["--fuzzy", "1", "conection", "a.txt"]).unwrap();
+        assert_eq!(Some(1), config.fuzzy);
+        assert!(config.pattern().is_match("connection reset"));
+
+        assert!(matches!(
+            build(&["--fuzzy", "-1", "duct", "a.txt"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            build(&["--fuzzy=1", "--regex", "duct", "a.txt"]),
+            Err(ConfigError::Conflict { .. })
+        ));
+        assert!(matches!(
+            build(&["--fuzzy=1", "-C2", "duct", "a.txt"]),
+            Err(ConfigError::Conflict { .. })
+        ));
+        match build(&["--fuzzy=1", "-r", "tape", "duct", "a.txt"]) {
+            Err(ConfigError::Conflict { flag, other }) => {
+                assert_eq!(("--fuzzy", "--replace"), (flag.as_str(), other.as_str()))
+            }
+            other => panic!("expected a conflict, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn replace_options() {
+        let config = build(&["-r", "$1", "--regex", "(d)uct", "a.txt"]).unwrap();
+        assert_eq!(Some("$1".to_string()), config.replace);
+        assert!(!config.in_place);
+
+        let args = [
+            "--replace=x",
+            "--in-place",
+            "--backup",
+            ".orig",
+            "duct",
+            "a.txt",
+        ];
+        let config = build(&args).unwrap();
+        assert!(config.in_place);
+        assert_eq!(Some(".orig".to_string()), config.backup_suffix);
+
+        assert!(matches!(
+            build(&["--in-place", "duct", "a.txt"]),
+            Err(ConfigError::Requires { .. })
+        ));
+        assert!(matches!(
+            build(&["-r", "x", "--backup", ".bak", "duct", "a.txt"]),
+            Err(ConfigError::Requires { .. })
+        ));
+        assert!(matches!(
+            build(&["-r", "x", "--in-place", "-z", "duct", "a.txt"]),
+            Err(ConfigError::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn follow() {
+        let config = build(&["--follow", "ERROR", "app.log"]).unwrap();
+        assert!(config.follow);
+
+        for args in [
+            &["--follow", "ERROR", "a.log", "b.log"][..],
+            &["--follow", "ERROR", "-"],
+            &["--follow", "-c", "ERROR", "a.log"],
+        ] {
+            assert!(matches!(build(args), Err(ConfigError::Conflict { .. })));
+        }
+    }
+
+    #[test]
+    fn structured_fields() {
+        let config = build(&["--csv-column", "city", "Porto", "a.csv"]).unwrap();
+        assert_eq!(Some(Field::CsvColumn("city".to_string())), config.field);
+
+        let config = build(&["--json-path=.ctx.user", "ana", "a.jsonl"]).unwrap();
+        assert_eq!(Field::json_path(".ctx.user"), config.field);
+
+        assert!(matches!(
+            build(&["--json-path", "ctx", "ana", "a.jsonl"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        match build(&["--json-path", ".a", "--csv-column", "a", "x", "a.jsonl"]) {
+            Err(ConfigError::Conflict { flag, other }) => {
+                assert_eq!(
+                    ("--csv-column", "--json-path"),
+                    (flag.as_str(), other.as_str())
+                )
+            }
+            other => panic!("expected a conflict, got {:?}", other.map(|_| ())),
+        }
+
+        let args = [
+            "--csv-column",
+            "city",
+            "--csv-column",
+            "zip",
+            "Porto",
+            "a.csv",
+        ];
+        let config = build(&args).unwrap();
+        assert_eq!(Some(Field::CsvColumn("zip".to_string())), config.field);
+        assert!(matches!(
+            build(&["--csv-column", "city", "-C1", "Porto", "a.csv"]),
+            Err(ConfigError::Conflict { .. })
+        ));
+    }
+}
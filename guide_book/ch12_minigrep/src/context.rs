@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::matcher::Matcher;
+use crate::stream::{LineMatch, LineReader, Pattern};
+
+/// One entry of a search that reports surrounding lines.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SearchResult {
+    Match(LineMatch),
+    /// A non-matching line shown because it is near a match.
+    Context(LineMatch),
+    /// The lines before and after this are not adjacent; printed as `--`.
+    Break,
+}
+
+/// Like [`SearchStream`](crate::SearchStream), but also yields the lines
+/// around each match. Overlapping windows are merged so no line is
+/// reported twice, and a [`SearchResult::Break`] separates groups.
+pub struct ContextStream<R, M = Pattern> {
+    lines: LineReader<R>,
+    matcher: M,
+    invert_match: bool,
+    before: usize,
+    after: usize,
+    // The most recent non-matching lines, kept for `before` context.
+    history: VecDeque<LineMatch>,
+    // How many more lines still belong to the last match's `after` context.
+    after_remaining: usize,
+    last_emitted: Option<usize>,
+    pending: VecDeque<SearchResult>,
+}
+
+impl<R: BufRead, M: Matcher> ContextStream<R, M> {
+    pub(crate) fn new(
+        lines: LineReader<R>,
+        matcher: M,
+        invert_match: bool,
+        before: usize,
+        after: usize,
+    ) -> ContextStream<R, M> {
+        ContextStream {
+            lines,
+            matcher,
+            invert_match,
+            before,
+            after,
+            history: VecDeque::with_capacity(before),
+            after_remaining: 0,
+            last_emitted: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// How much of the input has been consumed so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.lines.bytes_read()
+    }
+
+    fn emit(&mut self, result: SearchResult) {
+        let line_number = match &result {
+            SearchResult::Match(line) | SearchResult::Context(line) => line.line_number,
+            SearchResult::Break => unreachable!("breaks are only inserted here"),
+        };
+
+        let has_context = self.before > 0 || self.after > 0;
+        if let Some(last) = self.last_emitted {
+            if has_context && line_number > last + 1 {
+                self.pending.push_back(SearchResult::Break);
+            }
+        }
+
+        self.last_emitted = Some(line_number);
+        self.pending.push_back(result);
+    }
+}
+
+impl<R: BufRead, M: Matcher> Iterator for ContextStream<R, M> {
+    type Item = io::Result<SearchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let line = match self.lines.next_line()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+
+            let found = self.matcher.is_match(line.text);
+            let is_match = found != self.invert_match;
+            let line = LineMatch::new(&line, &self.matcher, found && is_match);
+
+            if is_match {
+                while let Some(previous) = self.history.pop_front() {
+                    self.emit(SearchResult::Context(previous));
+                }
+                self.emit(SearchResult::Match(line));
+                self.after_remaining = self.after;
+            } else if self.after_remaining > 0 {
+                self.after_remaining -= 1;
+                self.emit(SearchResult::Context(line));
+            } else if self.before > 0 {
+                if self.history.len() == self.before {
+                    self.history.pop_front();
+                }
+                self.history.push_back(line);
+            }
+        }
+
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_reader;
+
+    // Renders results the way grep does, with `:` for matches and `-` for context.
+    fn render(query: &str, contents: &str, before: usize, after: usize) -> Vec<String> {
+        search_reader(Pattern::literal(query), contents.as_bytes())
+            .with_context(before, after)
+            .map(|result| match result.unwrap() {
+                SearchResult::Match(m) => format!("{}:{}", m.line_number, m.line),
+                SearchResult::Context(c) => format!("{}-{}", c.line_number, c.line),
+                SearchResult::Break => "--".to_string(),
+            })
+            .collect()
+    }
+
+    const LOG: &str = "\
+one
+two ERROR
+three
+four
+five
+six
+seven ERROR
+eight";
+
+    #[test]
+    fn after_context() {
+        assert_eq!(
+            vec!["2:two ERROR", "3-three", "--", "7:seven ERROR", "8-eight"],
+            render("ERROR", LOG, 0, 1)
+        );
+    }
+
+    #[test]
+    fn before_context() {
+        assert_eq!(
+            vec![
+                "1-one",
+                "2:two ERROR",
+                "--",
+                "5-five",
+                "6-six",
+                "7:seven ERROR"
+            ],
+            render("ERROR", LOG, 2, 0)
+        );
+    }
+
+    #[test]
+    fn overlapping_windows_are_merged() {
+        assert_eq!(
+            vec![
+                "1-one",
+                "2:two ERROR",
+                "3-three",
+                "4-four",
+                "5-five",
+                "6-six",
+                "7:seven ERROR",
+                "8-eight"
+            ],
+            render("ERROR", LOG, 2, 2)
+        );
+    }
+
+    #[test]
+    fn no_separators_without_context() {
+        assert_eq!(
+            vec!["2:two ERROR", "7:seven ERROR"],
+            render("ERROR", LOG, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::io::{self, BufRead, BufReader};
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Format {
+    /// Recognizes a compressed stream by its magic bytes rather than by
+    /// its file name, so renamed or extensionless files still work.
+    pub fn detect(header: &[u8]) -> Option<Format> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(Format::Bzip2)
+        } else {
+            None
+        }
+    }
+}
+
+/// Wraps `reader` in a decoder if it starts with a known compression
+/// format, or hands it back unchanged if it doesn't.
+///
+/// Multi-member streams, such as several gzip files concatenated by a
+/// log rotator, are decoded in full.
+pub fn reader<'a>(mut reader: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    let decoded: Box<dyn BufRead> = match Format::detect(reader.fill_buf()?) {
+        None => return Ok(Box::new(reader)),
+        Some(Format::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Some(Format::Zstd) => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        Some(Format::Bzip2) => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+    };
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const TEXT: &str = "INFO started\nERROR 503 upstream\n";
+
+    fn decode(bytes: &[u8]) -> String {
+        let mut text = String::new();
+        reader(bytes).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(Some(Format::Gzip), Format::detect(&compressed));
+        assert_eq!(TEXT, decode(&compressed));
+    }
+
+    #[test]
+    fn concatenated_gzip_members() {
+        let mut compressed = Vec::new();
+        for part in TEXT.split_inclusive('\n') {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+
+        assert_eq!(TEXT, decode(&compressed));
+    }
+
+    #[test]
+    fn zstd() {
+        let compressed = zstd::encode_all(TEXT.as_bytes(), 0).unwrap();
+
+        assert_eq!(Some(Format::Zstd), Format::detect(&compressed));
+        assert_eq!(TEXT, decode(&compressed));
+    }
+
+    #[test]
+    fn bzip2() {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(Some(Format::Bzip2), Format::detect(&compressed));
+        assert_eq!(TEXT, decode(&compressed));
+    }
+
+    #[test]
+    fn plain_text_passes_through() {
+        assert_eq!(None, Format::detect(TEXT.as_bytes()));
+        assert_eq!(TEXT, decode(TEXT.as_bytes()));
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{self, BufRead, BufReader};
+
+use encoding_rs::{Encoding, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+/// Looks up an encoding by any of its WHATWG labels, e.g. "utf-16le",
+/// "latin1" or "shift_jis", ignoring case.
+pub fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// Transcodes `reader` to UTF-8 if it starts with a byte order mark or
+/// `encoding` names something other than UTF-8. A BOM wins over
+/// `encoding`, and is stripped from the output either way.
+///
+/// UTF-8 input is handed back untouched, so byte offsets still point
+/// into the original file; invalid sequences in it are replaced line by
+/// line when searching instead.
+pub fn reader<'a>(
+    mut reader: impl BufRead + 'a,
+    encoding: Option<&'static Encoding>,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let bom = Encoding::for_bom(reader.fill_buf()?);
+    let explicit = encoding.filter(|&encoding| encoding != UTF_8);
+
+    if bom.is_none() && explicit.is_none() {
+        return Ok(Box::new(reader));
+    }
+
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(explicit)
+        .build(reader);
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn decode(bytes: &[u8], label: Option<&str>) -> String {
+        let encoding = label.map(|label| for_label(label).unwrap());
+        let mut text = String::new();
+        reader(bytes, encoding)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let bytes = b"\xff\xfeh\x00i\x00\n\x00";
+        assert_eq!("hi\n", decode(bytes, None));
+
+        let bytes = b"\xfe\xff\x00h\x00i";
+        assert_eq!("hi", decode(bytes, None));
+    }
+
+    #[test]
+    fn utf8_bom_is_stripped() {
+        assert_eq!("hi", decode(b"\xef\xbb\xbfhi", None));
+    }
+
+    #[test]
+    fn explicit_encoding() {
+        assert_eq!("café", decode(b"caf\xe9", Some("latin1")));
+        assert_eq!("hi", decode(b"h\x00i\x00", Some("UTF-16LE")));
+        assert!(for_label("no-such-encoding").is_none());
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ConfigError;
+
+/// Everything that can make minigrep fail, for the binary to pick an
+/// exit status and message by.
+#[derive(Debug)]
+pub enum Error {
+    /// The command line doesn't make sense.
+    Usage(ConfigError),
+    /// The query isn't a valid regex or `--expr` expression.
+    InvalidPattern(ConfigError),
+    /// A file couldn't be opened or read, or the output written.
+    Io { path: PathBuf, source: io::Error },
+    /// A file was read but its contents couldn't be decoded: text that
+    /// isn't valid in its encoding, corrupt compressed data, malformed
+    /// CSV and so on.
+    Encoding { path: PathBuf, source: io::Error },
+}
+
+impl Error {
+    /// Wraps an error that came up while reading or writing `path`.
+    pub(crate) fn io(path: &Path, source: io::Error) -> Error {
+        let path = path.to_path_buf();
+        match source.kind() {
+            io::ErrorKind::InvalidData => Error::Encoding { path, source },
+            _ => Error::Io { path, source },
+        }
+    }
+
+    /// The file the error is about, if it's about one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Io { path, .. } | Error::Encoding { path, .. } => Some(path),
+            Error::Usage(_) | Error::InvalidPattern(_) => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(err: ConfigError) -> Error {
+        match err {
+            ConfigError::InvalidPattern(_) | ConfigError::InvalidQuery(_) => {
+                Error::InvalidPattern(err)
+            }
+            // Unlike the other flags, this one fails on a file.
+            ConfigError::PatternFile { path, source } => Error::io(Path::new(&path), source),
+            _ => Error::Usage(err),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(err) | Error::InvalidPattern(err) => write!(f, "{}", err),
+            Error::Io { path, source } | Error::Encoding { path, source } => {
+                write!(f, "{}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Usage(err) | Error::InvalidPattern(err) => Some(err),
+            Error::Io { source, .. } | Error::Encoding { source, .. } => Some(source),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors() {
+        let err = Error::io(Path::new("a.txt"), io::ErrorKind::NotFound.into());
+        assert!(matches!(err, Error::Io { .. }));
+        assert_eq!(Some(Path::new("a.txt")), err.path());
+
+        let err = Error::io(Path::new("a.gz"), io::ErrorKind::InvalidData.into());
+        assert!(matches!(err, Error::Encoding { .. }));
+
+        let err = Error::from(ConfigError::MissingQuery);
+        assert!(matches!(err, Error::Usage(_)));
+        assert_eq!(None, err.path());
+
+        let err = Error::from(ConfigError::PatternFile {
+            path: "patterns.txt".to_string(),
+            source: io::ErrorKind::NotFound.into(),
+        });
+        assert_eq!(Some(Path::new("patterns.txt")), err.path());
+    }
+
+    #[test]
+    fn messages_name_the_path() {
+        let source = io::Error::new(io::ErrorKind::PermissionDenied, "Permission denied");
+        let err = Error::io(Path::new("secret.txt"), source);
+        assert_eq!("secret.txt: Permission denied", err.to_string());
+    }
+}
@@ -0,0 +1,295 @@
+use std::ops::Range;
+
+use caseless::Caseless;
+use unicode_normalization::char::{canonical_combining_class, compose};
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Canonical composition, e.g. "e\u{301}" becomes "é".
+    Nfc,
+    /// Canonical decomposition, e.g. "é" becomes "e\u{301}".
+    Nfd,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FoldOptions {
+    /// Apply Unicode full case folding, so "STRASSE" matches "straße".
+    pub case_fold: bool,
+    /// Use the Turkish and Azerbaijani dotted/dotless i mappings
+    /// ('I' folds to 'ı' and 'İ' to 'i') instead of the default ones.
+    pub turkic: bool,
+    /// Bring both sides into the same normalization form first, so
+    /// precomposed and decomposed spellings of a character match.
+    pub normalization: Option<Normalization>,
+}
+
+/// A literal query that is compared against lines after both have been
+/// case folded and/or normalized, while still reporting match ranges as
+/// byte offsets into the original, unfolded line.
+#[derive(Clone, Debug)]
+pub struct FoldedQuery {
+    folded: String,
+    options: FoldOptions,
+}
+
+impl FoldedQuery {
+    pub fn new(query: &str, options: FoldOptions) -> FoldedQuery {
+        FoldedQuery {
+            folded: fold_text(query, options),
+            options,
+        }
+    }
+
+    pub fn options(&self) -> FoldOptions {
+        self.options
+    }
+
+    /// The query after folding, as it is looked for in folded lines.
+    pub fn folded(&self) -> &str {
+        &self.folded
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        fold_text(line, self.options).contains(self.folded.as_str())
+    }
+
+    pub fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        let (folded, origins) = fold(line, self.options);
+
+        let mut found: Vec<Range<usize>> = folded
+            .match_indices(self.folded.as_str())
+            .map(|(folded_start, found)| {
+                // Widen to whole original segments, since folding can turn
+                // one char into several (ß into "ss") and back. An empty
+                // query also matches at the very end, past every origin.
+                let start = match origins.get(folded_start) {
+                    Some(origin) => origin.start,
+                    None => line.len(),
+                };
+                let end = if found.is_empty() {
+                    start
+                } else {
+                    origins[folded_start + found.len() - 1].end
+                };
+                start..end
+            })
+            .collect();
+        // Empty matches inside one segment all land on the same spot.
+        found.dedup();
+        found
+    }
+}
+
+/// Folds `text` according to `options`, also returning, for every byte of
+/// the result, the byte range of `text` it was produced from.
+pub fn fold(text: &str, options: FoldOptions) -> (String, Vec<Range<usize>>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+
+    for segment in segments(text, options.normalization) {
+        let before = folded.len();
+        fold_segment(&text[segment.clone()], options, &mut folded);
+        origins.extend(std::iter::repeat_n(segment, folded.len() - before));
+    }
+
+    (folded, origins)
+}
+
+/// Like [`fold`], but without working out where each byte came from,
+/// for when only the folded text is needed.
+pub fn fold_text(text: &str, options: FoldOptions) -> String {
+    let mut folded = String::with_capacity(text.len());
+    match options.normalization {
+        // Every char is a segment of its own, so fold them all at once.
+        None => fold_segment(text, options, &mut folded),
+        Some(_) => {
+            for segment in segments(text, options.normalization) {
+                fold_segment(&text[segment], options, &mut folded);
+            }
+        }
+    }
+    folded
+}
+
+// Splits `text` into the smallest pieces that can be folded and normalized
+// independently. Without normalization that's every char; with it, a
+// starter together with the combining marks that follow it, plus any
+// further starters it would compose with (Hangul jamo, for instance).
+fn segments(text: &str, normalization: Option<Normalization>) -> Vec<Range<usize>> {
+    let mut segments: Vec<Range<usize>> = Vec::new();
+    let mut previous = None;
+
+    for (start, c) in text.char_indices() {
+        let end = start + c.len_utf8();
+        let joins_previous = match (normalization, previous) {
+            (None, _) | (_, None) => false,
+            (Some(_), Some(previous)) => {
+                canonical_combining_class(c) != 0 || compose(previous, c).is_some()
+            }
+        };
+
+        match (segments.last_mut(), previous) {
+            (Some(last), Some(p)) if joins_previous => {
+                last.end = end;
+                // Track what the segment composes to so far, so e.g. an LV
+                // syllable can still pick up its trailing T jamo.
+                previous = Some(compose(p, c).unwrap_or(c));
+            }
+            _ => {
+                segments.push(start..end);
+                previous = Some(c);
+            }
+        }
+    }
+
+    segments
+}
+
+fn fold_segment(segment: &str, options: FoldOptions, out: &mut String) {
+    let chars = segment.chars().map(|c| match c {
+        'I' if options.case_fold && options.turkic => 'ı',
+        'İ' if options.case_fold && options.turkic => 'i',
+        c => c,
+    });
+
+    let normalization = match options.normalization {
+        None if options.case_fold => return out.extend(chars.default_case_fold()),
+        None => return out.extend(chars),
+        Some(normalization) => normalization,
+    };
+
+    // Canonical caseless matching as defined by Unicode (D145):
+    // NFD(fold(NFD(x))), then recomposed if NFC was asked for.
+    let decomposed: String = chars.nfd().collect();
+    let decomposed: String = if options.case_fold {
+        decomposed.chars().default_case_fold().nfd().collect()
+    } else {
+        decomposed
+    };
+
+    match normalization {
+        Normalization::Nfd => out.push_str(&decomposed),
+        Normalization::Nfc => out.extend(decomposed.nfc()),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn caseless() -> FoldOptions {
+        FoldOptions {
+            case_fold: true,
+            ..FoldOptions::default()
+        }
+    }
+
+    #[test]
+    fn full_case_folding() {
+        let query = FoldedQuery::new("STRASSE", caseless());
+        assert_eq!(vec![4..11], query.find_iter("Die Straße"));
+
+        // Both sigma forms fold to the same letter.
+        let query = FoldedQuery::new("ΟΔΟΣ", caseless());
+        assert!(query.is_match("στην οδος"));
+        assert!(query.is_match("στην οδοσ"));
+    }
+
+    #[test]
+    fn turkic_dotless_i() {
+        let default = FoldedQuery::new("ISTANBUL", caseless());
+        assert!(default.is_match("istanbul"));
+        assert!(!default.is_match("ıstanbul"));
+
+        let turkic = FoldOptions {
+            turkic: true,
+            ..caseless()
+        };
+        let query = FoldedQuery::new("ISTANBUL", turkic);
+        assert!(query.is_match("ıstanbul"));
+        assert!(!query.is_match("istanbul"));
+
+        let query = FoldedQuery::new("İstanbul", turkic);
+        assert!(query.is_match("istanbul"));
+    }
+
+    #[test]
+    fn composed_and_decomposed_match_when_normalized() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301} au lait";
+
+        let plain = FoldedQuery::new(composed, FoldOptions::default());
+        assert!(!plain.is_match(decomposed));
+
+        for normalization in [Normalization::Nfc, Normalization::Nfd] {
+            let options = FoldOptions {
+                normalization: Some(normalization),
+                ..FoldOptions::default()
+            };
+            let query = FoldedQuery::new(composed, options);
+            assert_eq!(vec![0..6], query.find_iter(decomposed));
+
+            let query = FoldedQuery::new(decomposed, options);
+            assert!(!query.is_match("CAF\u{c9} au lait"));
+        }
+
+        let options = FoldOptions {
+            normalization: Some(Normalization::Nfc),
+            ..caseless()
+        };
+        let query = FoldedQuery::new("CAFE\u{301}", options);
+        assert_eq!(vec![0..5], query.find_iter("caf\u{e9}"));
+    }
+
+    #[test]
+    fn folding_without_origins_agrees() {
+        let nfc = FoldOptions {
+            normalization: Some(Normalization::Nfc),
+            ..caseless()
+        };
+        let turkic = FoldOptions {
+            turkic: true,
+            ..caseless()
+        };
+        for options in [FoldOptions::default(), caseless(), turkic, nfc] {
+            for text in [
+                "Die Straße",
+                "İSTANBUL",
+                "CAFE\u{301} \u{1112}\u{1161}\u{11ab}",
+            ] {
+                assert_eq!(fold(text, options).0, fold_text(text, options));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_query() {
+        let query = FoldedQuery::new("", caseless());
+        assert!(query.is_match(""));
+        assert_eq!(vec![0..0], query.find_iter(""));
+        assert_eq!(vec![0..0, 1..1, 3..3], query.find_iter("aß"));
+    }
+
+    #[test]
+    fn match_after_normalization_changes_length() {
+        let options = FoldOptions {
+            normalization: Some(Normalization::Nfc),
+            ..FoldOptions::default()
+        };
+        let query = FoldedQuery::new("x", options);
+        assert_eq!(vec![9..10], query.find_iter("e\u{301}e\u{301}e\u{301}x"));
+    }
+
+    #[test]
+    fn hangul_jamo_compose_across_starters() {
+        let options = FoldOptions {
+            normalization: Some(Normalization::Nfc),
+            ..FoldOptions::default()
+        };
+        // U+D55C is the precomposed form of the three jamo.
+        let query = FoldedQuery::new("\u{1112}\u{1161}\u{11ab}", options);
+        assert_eq!(vec![0..3], query.find_iter("\u{d55c}"));
+    }
+}
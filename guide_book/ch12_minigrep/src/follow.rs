@@ -0,0 +1,198 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads a file the way `tail -f` does: starting at its current end, and
+/// instead of stopping at EOF, waiting for more to be appended.
+///
+/// Log rotation is detected by polling the path. If the file shrinks it
+/// was truncated, so reading restarts from the top; if the path now
+/// names a different file (another inode), whatever is left of the old
+/// one is read first and then the new one is opened from the beginning.
+pub struct FollowReader {
+    path: PathBuf,
+    file: File,
+    id: Option<FileId>,
+    position: u64,
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+impl FollowReader {
+    pub fn open(path: &Path) -> io::Result<FollowReader> {
+        let mut file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+        let position = file.seek(SeekFrom::End(0))?;
+
+        Ok(FollowReader {
+            path: path.to_path_buf(),
+            file,
+            id,
+            position,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            idle_timeout: None,
+        })
+    }
+
+    /// How long to sleep between checks for new data.
+    pub fn poll_interval(mut self, interval: Duration) -> FollowReader {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Report EOF once nothing new has arrived for this long, rather
+    /// than waiting forever.
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> FollowReader {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    // Switches to the new file if the old one was rotated away or cut
+    // short. Returns whether there's something new to read.
+    fn reopen_if_rotated(&mut self) -> io::Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Between a rename and the new file showing up; try later.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        if file_id(&metadata) != self.id {
+            self.file = File::open(&self.path)?;
+            self.id = file_id(&self.file.metadata()?);
+            self.position = 0;
+            return Ok(true);
+        }
+
+        if metadata.len() < self.position {
+            self.position = self.file.seek(SeekFrom::Start(0))?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+}
+
+impl Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let started = Instant::now();
+
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                self.position += read as u64;
+                return Ok(read);
+            }
+
+            if self.reopen_if_rotated()? {
+                continue;
+            }
+            if self
+                .idle_timeout
+                .is_some_and(|timeout| started.elapsed() >= timeout)
+            {
+                return Ok(0);
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// Without inode numbers only truncation can be detected.
+#[cfg(not(unix))]
+type FileId = ();
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::{BufRead, BufReader, Write};
+
+    fn follow(path: &Path) -> BufReader<FollowReader> {
+        let reader = FollowReader::open(path)
+            .unwrap()
+            .poll_interval(Duration::from_millis(5))
+            .idle_timeout(Some(Duration::from_millis(200)));
+        BufReader::new(reader)
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn next_line(reader: &mut impl BufRead) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    #[test]
+    fn starts_at_the_end_and_sees_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut reader = follow(&path);
+        append(&path, "new 1\nnew ");
+        assert_eq!("new 1\n", next_line(&mut reader));
+
+        // A partial line is held back until the rest of it arrives.
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                append(&path, "2\n");
+            })
+        };
+        assert_eq!("new 2\n", next_line(&mut reader));
+        writer.join().unwrap();
+
+        assert_eq!("", next_line(&mut reader));
+    }
+
+    #[test]
+    fn truncation_restarts_from_the_top() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "a long line that came before\n").unwrap();
+
+        let mut reader = follow(&path);
+        fs::write(&path, "fresh\n").unwrap();
+        assert_eq!("fresh\n", next_line(&mut reader));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rotation_reopens_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "").unwrap();
+
+        let mut reader = follow(&path);
+        append(&path, "last words\n");
+        fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        fs::write(&path, "first words\n").unwrap();
+
+        assert_eq!("last words\n", next_line(&mut reader));
+        assert_eq!("first words\n", next_line(&mut reader));
+    }
+}
@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+/// One approximate occurrence of a [`FuzzyQuery`] within a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Byte range within the line.
+    pub range: Range<usize>,
+    /// The Levenshtein distance between the query and `range`.
+    pub distance: usize,
+}
+
+/// Finds substrings within a given Levenshtein distance of the query,
+/// e.g. "conection" or "connnection" for "connection" with a distance
+/// of 1. Distances are counted in chars, not bytes.
+#[derive(Clone, Debug)]
+pub struct FuzzyQuery {
+    query: Vec<char>,
+    max_distance: usize,
+    ignore_case: bool,
+}
+
+impl FuzzyQuery {
+    pub fn new(query: &str, max_distance: usize, ignore_case: bool) -> FuzzyQuery {
+        FuzzyQuery {
+            query: query.chars().map(|c| fold(c, ignore_case)).collect(),
+            max_distance,
+            ignore_case,
+        }
+    }
+
+    pub fn max_distance(&self) -> usize {
+        self.max_distance
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        !self.candidates(line).is_empty()
+    }
+
+    /// The edit distance of the closest match in `line`, if any.
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        self.candidates(line).iter().map(|m| m.distance).min()
+    }
+
+    /// The closest non-overlapping matches in `line`, in line order.
+    ///
+    /// Around every good match there are usually worse ones that are a
+    /// char longer or shorter, so the best candidates are picked first and
+    /// anything overlapping them is dropped.
+    pub fn find_iter(&self, line: &str) -> Vec<FuzzyMatch> {
+        let mut candidates = self.candidates(line);
+        candidates.sort_by_key(|m| (m.distance, m.range.start, usize::MAX - m.range.end));
+
+        let mut chosen: Vec<FuzzyMatch> = Vec::new();
+        for candidate in candidates {
+            let overlaps = chosen.iter().any(|m| {
+                m.range.start < candidate.range.end && candidate.range.start < m.range.end
+            });
+            if !overlaps {
+                chosen.push(candidate);
+            }
+        }
+
+        chosen.sort_by_key(|m| m.range.start);
+        chosen
+    }
+
+    // Sellers' algorithm: the edit distance DP, except that a match may
+    // start anywhere in the line for free. For every end position it also
+    // tracks where the cheapest alignment ending there started.
+    fn candidates(&self, line: &str) -> Vec<FuzzyMatch> {
+        let mut offsets: Vec<usize> = line.char_indices().map(|(i, _)| i).collect();
+        offsets.push(line.len());
+
+        let len = self.query.len();
+        if len <= self.max_distance {
+            // Deleting the whole query is cheap enough: everything matches.
+            return vec![FuzzyMatch {
+                range: 0..0,
+                distance: len,
+            }];
+        }
+
+        // `cost[j]` is the distance of `query[..j]` from the best
+        // substring ending at the current position; `start[j]` is where
+        // that substring starts, as a char index.
+        let mut cost: Vec<usize> = (0..=len).collect();
+        let mut start = vec![0; len + 1];
+        let mut candidates = Vec::new();
+
+        for (i, c) in line.chars().enumerate() {
+            let c = fold(c, self.ignore_case);
+            let mut diagonal = (cost[0], start[0]);
+            cost[0] = 0;
+            start[0] = i + 1;
+
+            for j in 1..=len {
+                let substitute = diagonal.0 + usize::from(self.query[j - 1] != c);
+                let options = [
+                    (substitute, diagonal.1),
+                    (cost[j] + 1, start[j]),
+                    (cost[j - 1] + 1, start[j - 1]),
+                ];
+                diagonal = (cost[j], start[j]);
+                // `min_by_key` keeps the first of equals, preferring the
+                // diagonal step.
+                let best = options.into_iter().min_by_key(|&(cost, _)| cost).unwrap();
+                (cost[j], start[j]) = best;
+            }
+
+            if cost[len] <= self.max_distance {
+                candidates.push(FuzzyMatch {
+                    range: offsets[start[len]]..offsets[i + 1],
+                    distance: cost[len],
+                });
+            }
+        }
+
+        candidates
+    }
+}
+
+// Simple per-char lowercasing; full case folding can change the number
+// of chars, which would skew the distances.
+fn fold(c: char, ignore_case: bool) -> char {
+    if ignore_case {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(query: &FuzzyQuery, line: &str) -> Vec<(String, usize)> {
+        query
+            .find_iter(line)
+            .into_iter()
+            .map(|m| (line[m.range].to_string(), m.distance))
+            .collect()
+    }
+
+    #[test]
+    fn exact_matches_have_distance_zero() {
+        let query = FuzzyQuery::new("timeout", 1, false);
+        assert_eq!(
+            vec![("timeout".to_string(), 0)],
+            found(&query, "read timeout after 30s")
+        );
+    }
+
+    #[test]
+    fn typos_within_the_distance() {
+        let query = FuzzyQuery::new("connection", 1, false);
+        // A deletion, an insertion and a substitution.
+        assert_eq!(
+            vec![("conection".to_string(), 1)],
+            found(&query, "conection reset")
+        );
+        assert_eq!(
+            vec![("connnection".to_string(), 1)],
+            found(&query, "connnection")
+        );
+        assert_eq!(
+            vec![("connectiom".to_string(), 1)],
+            found(&query, "connectiom")
+        );
+
+        assert!(!query.is_match("cnnectin"));
+        assert!(FuzzyQuery::new("connection", 2, false).is_match("cnnectin"));
+    }
+
+    #[test]
+    fn several_matches_per_line() {
+        let query = FuzzyQuery::new("error", 1, false);
+        assert_eq!(
+            vec![("eror".to_string(), 1), ("error".to_string(), 0)],
+            found(&query, "eror, then error")
+        );
+    }
+
+    #[test]
+    fn ranges_are_byte_offsets() {
+        let query = FuzzyQuery::new("CAFE", 1, true);
+        assert_eq!(
+            vec![FuzzyMatch {
+                range: 3..8,
+                distance: 1
+            }],
+            query.find_iter("Un café")
+        );
+    }
+
+    #[test]
+    fn short_queries_match_everything() {
+        let query = FuzzyQuery::new("ab", 2, false);
+        assert!(query.is_match(""));
+        assert!(query.is_match("xyz"));
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use caseless::Caseless;
+use tempfile::NamedTempFile;
+
+use crate::{encoding, walk, Pattern};
+
+/// Where the index of a directory is kept. The walk always skips it, so
+/// it's neither searched nor indexed itself.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep-index 1\n";
+
+/// Three consecutive bytes of case-folded text, packed into the low bits.
+pub type Trigram = u32;
+
+/// A trigram index of every file below a directory.
+///
+/// Trigrams are taken from the case-folded text, so one index serves both
+/// case-sensitive and `-i` literal searches: if a line contains the query,
+/// its folded form contains the folded query, and thereby all of its
+/// trigrams. Each file's trigrams are kept together with its mtime and
+/// size, so an update only has to reread the files that changed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Index {
+    files: BTreeMap<PathBuf, Entry>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    modified: (u64, u32),
+    len: u64,
+    // Sorted, for binary search.
+    trigrams: Vec<Trigram>,
+}
+
+/// What [`Index::update`] did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct UpdateStats {
+    pub files: usize,
+    pub reindexed: usize,
+    pub removed: usize,
+}
+
+impl Index {
+    /// Loads the index of `root`, or returns `None` if it has none.
+    pub fn open(root: &Path) -> io::Result<Option<Index>> {
+        match File::open(root.join(INDEX_FILE)) {
+            Ok(file) => Index::read(BufReader::new(file)).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Brings the index up to date with the files below `root`, rereading
+    /// only those that are new or whose mtime or size changed.
+    pub fn update(&mut self, root: &Path) -> io::Result<UpdateStats> {
+        let mut stats = UpdateStats::default();
+        let mut seen = HashSet::new();
+
+        for path in walk::files(root).filter_map(Result::ok) {
+            let Ok(relative) = path.strip_prefix(root) else {
+                continue;
+            };
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let modified = timestamp(metadata.modified()?);
+            seen.insert(relative.to_path_buf());
+            stats.files += 1;
+
+            let fresh = self
+                .files
+                .get(relative)
+                .is_some_and(|entry| entry.modified == modified && entry.len == metadata.len());
+            if fresh {
+                continue;
+            }
+
+            let Ok(text) = read_text(&path) else {
+                continue;
+            };
+            let entry = Entry {
+                modified,
+                len: metadata.len(),
+                trigrams: trigrams(&text),
+            };
+            self.files.insert(relative.to_path_buf(), entry);
+            stats.reindexed += 1;
+        }
+
+        let before = self.files.len();
+        self.files.retain(|path, _| seen.contains(path));
+        stats.removed = before - self.files.len();
+
+        Ok(stats)
+    }
+
+    /// Writes the index to `root`, replacing any earlier one atomically.
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let mut temp = NamedTempFile::new_in(root)?;
+        self.write(&mut BufWriter::new(&mut temp))?;
+        temp.persist(root.join(INDEX_FILE))
+            .map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    /// Whether the file at `relative` could contain a line with all of
+    /// `required`. Files the index doesn't know about always could.
+    pub fn may_contain(&self, relative: &Path, required: &[Trigram]) -> bool {
+        match self.files.get(relative) {
+            Some(entry) => required
+                .iter()
+                .all(|trigram| entry.trigrams.binary_search(trigram).is_ok()),
+            None => true,
+        }
+    }
+
+    // The format is the magic line followed by, for every file: its path,
+    // mtime (seconds and nanoseconds), size and trigrams, with lengths and
+    // numbers in little endian.
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u64(out, self.files.len() as u64)?;
+
+        for (path, entry) in &self.files {
+            let path = path.to_string_lossy();
+            write_u64(out, path.len() as u64)?;
+            out.write_all(path.as_bytes())?;
+            write_u64(out, entry.modified.0)?;
+            out.write_all(&entry.modified.1.to_le_bytes())?;
+            write_u64(out, entry.len)?;
+            write_u64(out, entry.trigrams.len() as u64)?;
+            for trigram in &entry.trigrams {
+                out.write_all(&trigram.to_le_bytes())?;
+            }
+        }
+
+        out.flush()
+    }
+
+    fn read(mut input: impl Read) -> io::Result<Index> {
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(damaged("not a minigrep index"));
+        }
+
+        let mut index = Index::default();
+        for _ in 0..read_u64(&mut input)? {
+            let path = String::from_utf8(read_bytes(&mut input)?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            let seconds = read_u64(&mut input)?;
+            let mut nanos = [0; 4];
+            input.read_exact(&mut nanos)?;
+            let len = read_u64(&mut input)?;
+
+            let count = read_u64(&mut input)?;
+            let mut trigrams = Vec::new();
+            for _ in 0..count {
+                let mut trigram = [0; 4];
+                input.read_exact(&mut trigram)?;
+                trigrams.push(u32::from_le_bytes(trigram));
+            }
+
+            let entry = Entry {
+                modified: (seconds, u32::from_le_bytes(nanos)),
+                len,
+                trigrams,
+            };
+            index.files.insert(PathBuf::from(path), entry);
+        }
+
+        Ok(index)
+    }
+}
+
+/// The trigrams every matching line must contain, or `None` if `pattern`
+/// can't be narrowed down this way (regexes, normalization, queries
+/// shorter than three bytes and so on).
+pub fn required_trigrams(pattern: &Pattern) -> Option<Vec<Trigram>> {
+    let query = match pattern {
+        Pattern::Literal(query) => query.as_str(),
+        Pattern::Folded(query) => {
+            let options = query.options();
+            if options.turkic || options.normalization.is_some() {
+                return None;
+            }
+            query.folded()
+        }
+        _ => return None,
+    };
+
+    let required = trigrams(query);
+    (!required.is_empty()).then_some(required)
+}
+
+/// The distinct trigrams of `text` after full case folding, sorted.
+pub fn trigrams(text: &str) -> Vec<Trigram> {
+    let folded: String = text.chars().default_case_fold().collect();
+    let mut trigrams: Vec<Trigram> = folded
+        .as_bytes()
+        .windows(3)
+        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], 0]))
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+// Decodes the file the same way a search would, so UTF-16 files with a
+// BOM are indexed by their text rather than their bytes.
+fn read_text(path: &Path) -> io::Result<String> {
+    let mut contents = Vec::new();
+    let file = BufReader::new(File::open(path)?);
+    encoding::reader(file, None)?.read_to_end(&mut contents)?;
+    Ok(String::from_utf8_lossy(&contents).into_owned())
+}
+
+fn timestamp(time: SystemTime) -> (u64, u32) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since_epoch.as_secs(), since_epoch.subsec_nanos())
+}
+
+fn write_u64(out: &mut impl Write, n: u64) -> io::Result<()> {
+    out.write_all(&n.to_le_bytes())
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+// Reads a length-prefixed string of bytes. The length is only trusted as
+// far as the input goes, so a damaged one can't ask for a huge allocation.
+fn read_bytes(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u64(input)?;
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(damaged("truncated minigrep index"));
+    }
+    Ok(bytes)
+}
+
+fn damaged(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn candidates(index: &Index, query: &str) -> Vec<&'static str> {
+        let required = required_trigrams(&Pattern::literal(query)).unwrap();
+        ["a.txt", "b.txt", "c.txt"]
+            .into_iter()
+            .filter(|path| index.may_contain(Path::new(path), &required))
+            .collect()
+    }
+
+    #[test]
+    fn narrows_down_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "connection reset\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "CONNECTION REFUSED\n").unwrap();
+
+        let mut index = Index::default();
+        index.update(dir.path()).unwrap();
+
+        assert_eq!(
+            vec!["a.txt", "b.txt", "c.txt"],
+            candidates(&index, "connection")
+        );
+        assert_eq!(vec!["a.txt", "c.txt"], candidates(&index, "reset"));
+        // c.txt isn't indexed, so it has to be searched anyway.
+        assert_eq!(vec!["c.txt"], candidates(&index, "timeout"));
+    }
+
+    #[test]
+    fn only_literal_queries_narrow() {
+        assert!(required_trigrams(&Pattern::literal("ab")).is_none());
+        assert!(required_trigrams(&Pattern::ignore_case("Straße")).is_some());
+
+        let regex = Pattern::Regex(regex::Regex::new("abc").unwrap());
+        assert!(required_trigrams(&regex).is_none());
+    }
+
+    #[test]
+    fn case_folded_trigrams() {
+        // "ß" folds to "ss", so "STRASSE" must find it.
+        assert_eq!(trigrams("strasse"), trigrams("Straße"));
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a.txt"), "alpha\n").unwrap();
+        fs::write(root.join("b.txt"), "beta\n").unwrap();
+
+        let mut index = Index::default();
+        let stats = index.update(root).unwrap();
+        assert_eq!((2, 2, 0), (stats.files, stats.reindexed, stats.removed));
+        index.save(root).unwrap();
+
+        let mut index = Index::open(root).unwrap().unwrap();
+        let stats = index.update(root).unwrap();
+        assert_eq!((2, 0, 0), (stats.files, stats.reindexed, stats.removed));
+
+        let a = File::options()
+            .write(true)
+            .open(root.join("a.txt"))
+            .unwrap();
+        a.set_len(0).unwrap();
+        a.set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        fs::remove_file(root.join("b.txt")).unwrap();
+
+        let stats = index.update(root).unwrap();
+        assert_eq!((1, 1, 1), (stats.files, stats.reindexed, stats.removed));
+        assert!(!index.may_contain(Path::new("a.txt"), &trigrams("alpha")));
+    }
+
+    #[test]
+    fn round_trips_through_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "alpha\n").unwrap();
+
+        let mut index = Index::default();
+        index.update(dir.path()).unwrap();
+        index.save(dir.path()).unwrap();
+
+        assert_eq!(Some(index), Index::open(dir.path()).unwrap());
+        assert!(Index::open(&dir.path().join("missing")).unwrap().is_none());
+
+        fs::write(dir.path().join(INDEX_FILE), "garbage").unwrap();
+        assert!(Index::open(dir.path()).is_err());
+
+        // A path length far past the end of the file.
+        let mut damaged = MAGIC.to_vec();
+        damaged.extend(1u64.to_le_bytes());
+        damaged.extend(u64::MAX.to_le_bytes());
+        damaged.extend(b"a.txt");
+        fs::write(dir.path().join(INDEX_FILE), damaged).unwrap();
+        let err = Index::open(dir.path()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+}
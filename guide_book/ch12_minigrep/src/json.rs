@@ -0,0 +1,165 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::stats::{FileStats, Stats};
+use crate::LineMatch;
+
+// Emits one JSON object per line, in the spirit of ripgrep's `--json`:
+// `begin` and `end` bracket the results of each file that had a match,
+// with `match` and `context` events in between and a final `summary`.
+
+pub(crate) fn begin(out: &mut impl Write, path: &Path) -> io::Result<()> {
+    emit(out, "begin", json!({ "path": text(path) }))
+}
+
+/// `kind` is either `"match"` or `"context"`.
+pub(crate) fn line(
+    out: &mut impl Write,
+    kind: &str,
+    path: &Path,
+    found: &LineMatch,
+) -> io::Result<()> {
+    let submatches: Vec<Value> = found
+        .matches
+        .iter()
+        .map(|range| {
+            json!({
+                "match": { "text": &found.line[range.clone()] },
+                "start": range.start,
+                "end": range.end,
+            })
+        })
+        .collect();
+
+    let mut data = json!({
+        "path": text(path),
+        "lines": { "text": found.line },
+        "line_number": found.line_number,
+        "absolute_offset": found.byte_offset,
+        "submatches": submatches,
+    });
+    if let Some(distance) = found.distance {
+        data["distance"] = json!(distance);
+    }
+    emit(out, kind, data)
+}
+
+pub(crate) fn end(
+    out: &mut impl Write,
+    path: &Path,
+    stats: FileStats,
+    elapsed: Duration,
+) -> io::Result<()> {
+    let mut totals = Stats::default();
+    totals.add(path, stats);
+    emit(
+        out,
+        "end",
+        json!({ "path": text(path), "stats": stats_json(&totals, elapsed) }),
+    )
+}
+
+pub(crate) fn summary(out: &mut impl Write, totals: &Stats) -> io::Result<()> {
+    emit(
+        out,
+        "summary",
+        json!({
+            "elapsed_total": duration(totals.elapsed),
+            "stats": stats_json(totals, totals.elapsed),
+        }),
+    )
+}
+
+fn emit(out: &mut impl Write, kind: &str, data: Value) -> io::Result<()> {
+    let event = json!({ "type": kind, "data": data });
+    serde_json::to_writer(&mut *out, &event)?;
+    writeln!(out)
+}
+
+// Paths that aren't valid UTF-8 are converted lossily.
+fn text(path: &Path) -> Value {
+    json!({ "text": path.to_string_lossy() })
+}
+
+fn stats_json(totals: &Stats, elapsed: Duration) -> Value {
+    json!({
+        "elapsed": duration(elapsed),
+        "searches": totals.files_searched,
+        "searches_with_match": totals.files_with_match,
+        "bytes_searched": totals.bytes_searched,
+        "matched_lines": totals.matched_lines,
+        "matches": totals.matches,
+    })
+}
+
+fn duration(elapsed: Duration) -> Value {
+    json!({
+        "secs": elapsed.as_secs(),
+        "nanos": elapsed.subsec_nanos(),
+        "human": format!("{:.6}s", elapsed.as_secs_f64()),
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn events(output: Vec<u8>) -> Vec<Value> {
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn match_event() {
+        let found = LineMatch {
+            line_number: 3,
+            byte_offset: 40,
+            line: "ERROR 503 and 504".to_string(),
+            matches: vec![6..9, 14..17],
+            distance: None,
+        };
+        let mut out = Vec::new();
+        line(&mut out, "match", Path::new("app.log"), &found).unwrap();
+
+        let event = &events(out)[0];
+        assert_eq!("match", event["type"]);
+        assert_eq!("app.log", event["data"]["path"]["text"]);
+        assert_eq!(3, event["data"]["line_number"]);
+        assert_eq!(40, event["data"]["absolute_offset"]);
+        assert_eq!("504", event["data"]["submatches"][1]["match"]["text"]);
+        assert_eq!(14, event["data"]["submatches"][1]["start"]);
+        assert_eq!(17, event["data"]["submatches"][1]["end"]);
+    }
+
+    #[test]
+    fn end_and_summary_stats() {
+        let stats = FileStats {
+            bytes_searched: 100,
+            matched_lines: 2,
+            matches: 3,
+        };
+        let mut totals = Stats::default();
+        totals.add(Path::new("a"), stats);
+        totals.add(Path::new("b"), FileStats::default());
+        totals.elapsed = Duration::from_millis(5);
+
+        let mut out = Vec::new();
+        end(&mut out, Path::new("a"), stats, Duration::from_millis(2)).unwrap();
+        summary(&mut out, &totals).unwrap();
+
+        let events = events(out);
+        assert_eq!(2, events[0]["data"]["stats"]["matched_lines"]);
+        assert_eq!(1, events[0]["data"]["stats"]["searches_with_match"]);
+        assert_eq!("summary", events[1]["type"]);
+        assert_eq!(2, events[1]["data"]["stats"]["searches"]);
+        assert_eq!(1, events[1]["data"]["stats"]["searches_with_match"]);
+        assert_eq!(5_000_000, events[1]["data"]["elapsed_total"]["nanos"]);
+    }
+}
@@ -1,76 +1,460 @@
-use std::env;
-use std::error::Error;
-use std::fs;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
+use encoding_rs::Encoding;
+use regex::Regex;
+
+use mmap::Contents;
+use printer::Printer;
+
+mod config;
+pub mod context;
+pub mod decompress;
+pub mod encoding;
+mod error;
+pub mod fold;
+pub mod follow;
+pub mod fuzzy;
+pub mod index;
+mod json;
+pub mod literal;
+pub mod matcher;
+mod mmap;
+pub mod multiline;
+mod output;
+pub mod parallel;
+mod printer;
+pub mod query;
+mod replace;
+pub mod searcher;
+pub mod settings;
+mod stats;
+pub mod stream;
+pub mod structured;
+pub mod walk;
+
+pub use config::{ColorChoice, Config, ConfigError};
+pub use context::{ContextStream, SearchResult};
+pub use error::Error;
+pub use fold::{FoldOptions, FoldedQuery, Normalization};
+pub use follow::FollowReader;
+pub use fuzzy::{FuzzyMatch, FuzzyQuery};
+pub use index::Index;
+pub use matcher::Matcher;
+pub use query::{Query, QueryError, QueryOptions};
+pub use searcher::{Searcher, Sink};
+pub use stats::{FileStats, Stats};
+pub use stream::{search_reader, LineMatch, Pattern, SearchStream};
+pub use structured::{Field, RecordStream};
+
+/// The path that stands for standard input, as in most Unix tools.
+const STDIN_PATH: &str = "-";
+
+/// What errors writing the output are reported against.
+const STDOUT_PATH: &str = "(standard output)";
+
+enum Input {
+    Stdin,
+    /// A file named on the command line.
+    Named(PathBuf),
+    /// A file found while walking a directory; binary files are skipped.
+    Walked(PathBuf),
+}
+
+impl Input {
+    fn path(&self) -> &Path {
+        match self {
+            Input::Stdin => Path::new("(standard input)"),
+            Input::Named(path) | Input::Walked(path) => path,
+        }
+    }
 }
 
-impl Config {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
+/// Searches every input `config` names, printing results to stdout, and
+/// returns what was searched and found.
+///
+/// An input that can't be searched is reported on stderr and skipped, and
+/// once every other input has been searched, the first such error is
+/// returned, having been reported too. The same goes for directories that
+/// can't be walked. Once a `-q` search has found a match, errors no longer
+/// count. If stdout is closed early, as by `minigrep ... | head`, the
+/// search just stops.
+pub fn run(config: Config) -> Result<Stats, Error> {
+    let started = Instant::now();
+    // Like grep, name the file on each line once more than one could match.
+    let show_path = config.paths.len() > 1 || config.paths.iter().any(|p| Path::new(p).is_dir());
+    let printer = Printer {
+        show_path,
+        line_number: config.line_number,
+        color: config.color.enabled(),
+    };
+    let (inputs, mut first_error) = inputs(&config);
+    let json = config.json;
+    let show_stats = config.stats;
+    let quiet = config.quiet;
+    let mut closed = false;
+    let mut totals = Stats::default();
+
+    // Says whether to search on.
+    let mut report = |input: &Input, result: io::Result<FileStats>| {
+        if closed {
+            return false;
+        }
+        match result {
+            Ok(stats) => totals.add(input.path(), stats),
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => closed = true,
+            Err(err) => {
+                let err = Error::io(input.path(), err);
+                eprintln!("minigrep: {}", err);
+                first_error.get_or_insert(err);
+            }
+        }
+        !(closed || (quiet && totals.matched_lines > 0))
+    };
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string."),
+    // -q can stop at the first match only if inputs are searched in turn.
+    if config.jobs == 1 || inputs.len() <= 1 || quiet {
+        // Nothing to overlap, so write straight through without buffering.
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for input in &inputs {
+            if !report(input, search_input(&config, input, printer, &mut out)) {
+                break;
+            }
+        }
+    } else {
+        let jobs = config.jobs;
+        let paths: Vec<PathBuf> = inputs
+            .iter()
+            .map(|input| input.path().to_path_buf())
+            .collect();
+        let config = Arc::new(config);
+        let search = {
+            let config = Arc::clone(&config);
+            move |input: Input| {
+                let mut out = Vec::new();
+                let result = search_input(&config, &input, printer, &mut out);
+                (input, out, result)
+            }
         };
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path."),
+        let searched = parallel::ordered_map(jobs, inputs, search, |(input, out, result)| {
+            let result = io::stdout().write_all(&out).and(result);
+            report(&input, result);
+        });
+        if let Err(lost) = searched {
+            let input = Input::Named(paths[lost.index].clone());
+            report(&input, Err(io::Error::other("the search panicked")));
+        }
+    }
+
+    totals.elapsed = started.elapsed();
+    if closed || (quiet && totals.matched_lines > 0) {
+        return Ok(totals);
+    }
+
+    let summary = if json {
+        // The summary event already has all of --stats in it.
+        json::summary(&mut io::stdout(), &totals)
+    } else if show_stats && !quiet {
+        write!(io::stdout(), "\n{}", totals)
+    } else {
+        Ok(())
+    };
+    match summary {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            let err = Error::io(Path::new(STDOUT_PATH), err);
+            eprintln!("minigrep: {}", err);
+            Err(err)
+        }
+        _ => first_error.map_or(Ok(totals), Err),
+    }
+}
+
+// Expands directories into the files below them, in a stable order.
+// Walking errors are reported as they come up, and the first is returned.
+fn inputs(config: &Config) -> (Vec<Input>, Option<Error>) {
+    let mut inputs = Vec::new();
+    let mut first_error = None;
+
+    for file_path in &config.paths {
+        let path = Path::new(file_path);
+
+        if file_path == STDIN_PATH {
+            inputs.push(Input::Stdin);
+        } else if path.is_dir() {
+            let narrowing = narrowing(config, path);
+            for entry in walk::files(path) {
+                match entry {
+                    Ok(file_path) => {
+                        if let Some((index, required)) = &narrowing {
+                            let relative = file_path.strip_prefix(path).unwrap_or(&file_path);
+                            if !index.may_contain(relative, required) {
+                                continue;
+                            }
+                        }
+                        inputs.push(Input::Walked(file_path));
+                    }
+                    Err(err) => {
+                        let (failed, source) = walk::split_error(err);
+                        let err = Error::io(failed.as_deref().unwrap_or(path), source);
+                        eprintln!("minigrep: {}", err);
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+        } else {
+            inputs.push(Input::Named(path.to_path_buf()));
+        }
+    }
+
+    (inputs, first_error)
+}
+
+// If `root` has been indexed and the pattern allows it, returns the index
+// along with the trigrams a file must have to be searched. Files changed
+// since indexing are reread, but only in memory: a search never writes
+// the index, that's up to `minigrep index`.
+fn narrowing(config: &Config, root: &Path) -> Option<(Index, Vec<index::Trigram>)> {
+    // Inverted searches match files without the trigrams, and the index
+    // knows nothing about decompressed or explicitly decoded contents, or
+    // about CSV and JSON escapes.
+    if config.invert_match
+        || config.search_zip
+        || config.encoding.is_some()
+        || config.field.is_some()
+    {
+        return None;
+    }
+    let required = index::required_trigrams(&config.pattern())?;
+
+    let mut index = match Index::open(root) {
+        Ok(index) => index?,
+        Err(err) => {
+            eprintln!("minigrep: {}: ignoring index: {}", root.display(), err);
+            return None;
+        }
+    };
+    if let Err(err) = index.update(root) {
+        eprintln!("minigrep: {}: ignoring index: {}", root.display(), err);
+        return None;
+    }
+
+    Some((index, required))
+}
+
+fn search_input(
+    config: &Config,
+    input: &Input,
+    printer: Printer,
+    out: &mut impl Write,
+) -> io::Result<FileStats> {
+    if let (true, Some(template)) = (config.in_place, &config.replace) {
+        let backup_suffix = config.backup_suffix.as_deref();
+        return match input {
+            Input::Stdin => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "standard input can't be edited in place",
+            )),
+            Input::Named(path) | Input::Walked(path) => {
+                replace::in_place(path, &config.pattern(), template, backup_suffix)
+            }
         };
+    }
+
+    let path = match input {
+        Input::Named(path) | Input::Walked(path) if in_memory(config, path) => path,
+        _ => {
+            let reader = match input {
+                Input::Stdin => open(config, io::stdin().lock())?,
+                Input::Named(path) if config.follow => {
+                    open(config, BufReader::new(FollowReader::open(path)?))?
+                }
+                Input::Named(path) | Input::Walked(path) => {
+                    open(config, BufReader::new(File::open(path)?))?
+                }
+            };
+            return search_stream(config, input, reader, printer, out);
+        }
+    };
+
+    let contents = Contents::open(path)?;
+    if Encoding::for_bom(&contents).is_some() {
+        let reader = encoding::reader(&contents[..], None)?;
+        return search_stream(config, input, reader, printer, out);
+    }
+
+    let binary = !config.text && walk::is_binary(&contents);
+    match presentation(input, printer, binary) {
+        Some((printer, binary)) => {
+            let pattern = config.pattern();
+            let mut output = output::for_config(config, &pattern, path, printer, binary, out);
+            searcher(config).search_slice(&pattern, &contents, &mut output)?;
+            Ok(output.stats())
+        }
+        None => Ok(FileStats::default()),
+    }
+}
+
+// Plain files are searched in one go, memory-mapped if they're large,
+// unless they have to be decompressed, transcoded or parsed on the way.
+fn in_memory(config: &Config, path: &Path) -> bool {
+    !config.follow
+        && !config.search_zip
+        && config.encoding.is_none()
+        && config.field.is_none()
+        && path.is_file()
+}
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+fn search_stream(
+    config: &Config,
+    input: &Input,
+    mut reader: impl BufRead,
+    printer: Printer,
+    out: &mut impl Write,
+) -> io::Result<FileStats> {
+    // Sniffed after decompression and decoding, so compressed text and
+    // UTF-16 (which is full of NUL bytes) still count as text.
+    let binary = !config.text && walk::is_binary(reader.fill_buf()?);
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+    match presentation(input, printer, binary) {
+        Some((printer, binary)) => {
+            print_matches(config, input.path(), reader, printer, binary, out)
+        }
+        None => Ok(FileStats::default()),
+    }
+}
+
+// Binary files found by walking are skipped, and files found by walking
+// are always printed with their path.
+fn presentation(input: &Input, printer: Printer, binary: bool) -> Option<(Printer, bool)> {
+    match input {
+        Input::Walked(_) if binary => None,
+        Input::Walked(_) => {
+            let printer = Printer {
+                show_path: true,
+                ..printer
+            };
+            Some((printer, false))
+        }
+        _ => Some((printer, binary)),
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+fn searcher(config: &Config) -> Searcher {
+    Searcher::new()
+        .invert_match(config.invert_match)
+        .before_context(config.before_context)
+        .after_context(config.after_context)
+        .multi_line(config.multiline)
+}
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
+fn open<'a>(config: &Config, reader: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    let reader = if config.search_zip {
+        decompress::reader(reader)?
     } else {
-        search(&config.query, &contents)
+        Box::new(reader)
     };
+    encoding::reader(reader, config.encoding)
+}
+
+fn print_matches(
+    config: &Config,
+    path: &Path,
+    reader: impl BufRead,
+    printer: Printer,
+    binary: bool,
+    out: &mut impl Write,
+) -> io::Result<FileStats> {
+    let pattern = config.pattern();
+    let mut output = output::for_config(config, &pattern, path, printer, binary, out);
+
+    if let Some(field) = &config.field {
+        let mut records =
+            RecordStream::new(field, pattern.clone(), reader)?.invert_match(config.invert_match);
+        for found in records.by_ref() {
+            if !output.matched(&found?)? {
+                break;
+            }
+        }
+        output.finish(records.bytes_read())?;
+    } else {
+        searcher(config).search_reader(&pattern, reader, &mut output)?;
+    }
+
+    Ok(output.stats())
+}
+
+/// Builds the trigram index of each directory in `dirs`, or brings an
+/// existing one up to date. Later searches of those directories use it
+/// to skip files that can't match.
+pub fn index(dirs: &[String]) -> Result<(), Error> {
+    if dirs.is_empty() {
+        return Err(ConfigError::MissingFilePath.into());
+    }
 
-    for line in results {
-        println!("{}", line);
+    for dir in dirs {
+        let root = Path::new(dir);
+        if !root.is_dir() {
+            let err = io::Error::new(io::ErrorKind::InvalidInput, "not a directory");
+            return Err(Error::io(root, err));
+        }
+        // A damaged index is simply rebuilt from scratch.
+        let mut index = Index::open(root).ok().flatten().unwrap_or_default();
+        let stats = index.update(root).map_err(|err| Error::io(root, err))?;
+        index.save(root).map_err(|err| Error::io(root, err))?;
+        println!(
+            "{}: {} files, {} (re)indexed, {} removed",
+            dir, stats.files, stats.reindexed, stats.removed
+        );
     }
 
     Ok(())
 }
 
+/// Convenience wrapper for when the whole input is already in memory;
+/// see [`search_reader`] for the streaming version.
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
+    if !literal::is_searchable(query.as_bytes()) {
+        return search_pattern(&Pattern::literal(query), contents);
+    }
+    literal::MatchingLines::new(contents.as_bytes(), query.as_bytes())
+        .map(|line| &contents[line])
         .collect()
 }
 
 pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = Vec::new();
+    search_pattern(&Pattern::ignore_case(query), contents)
+}
 
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
-        }
-    }
+/// Like [`search`], but `query` may contain line breaks; each result is
+/// the run of lines a match covers.
+pub fn search_multiline<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    multiline::blocks(&Pattern::literal(query), contents)
+        .into_iter()
+        .map(|block| &contents[block.lines])
+        .collect()
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<&'a str> {
+    search_pattern(&Pattern::Regex(regex.clone()), contents)
+}
 
-    results
+fn search_pattern<'a>(pattern: &Pattern, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| pattern.is_match(line))
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use index::INDEX_FILE;
+    use std::fs;
 
     #[test]
     fn case_sensitive() {
@@ -98,4 +482,172 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
+
+    fn build(args: &[&str]) -> Config {
+        let args = std::iter::once("minigrep").chain(args.iter().copied());
+        Config::from_args(args.map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn invert_match_with_line_numbers() {
+        let config = build(&["-v", "-n", "a", "poem.txt"]);
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        let found: Vec<(usize, String)> = search_reader(config.pattern(), contents.as_bytes())
+            .invert_match(config.invert_match)
+            .map(|m| m.map(|m| (m.line_number, m.line)).unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![(1, "Rust:".to_string()), (3, "Pick three.".to_string())],
+            found
+        );
+    }
+
+    fn build_regex(pattern: &str) -> Result<Config, ConfigError> {
+        let args = ["minigrep", "--regex", pattern, "poem.txt"];
+        Config::from_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn regex_anchors() {
+        let config = build_regex(r"^Pick|\.$").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Don't Pick me";
+
+        assert_eq!(
+            vec!["safe, fast, productive.", "Pick three."],
+            search_regex(config.regex.as_ref().unwrap(), contents)
+        );
+    }
+
+    #[test]
+    fn regex_classes() {
+        let config = build_regex(r"ERROR \d{3}").unwrap();
+        let contents = "\
+INFO 200 ok
+ERROR 503 upstream
+ERROR abc
+ERROR 42";
+
+        assert_eq!(
+            vec!["ERROR 503 upstream"],
+            search_regex(config.regex.as_ref().unwrap(), contents)
+        );
+    }
+
+    #[test]
+    fn regex_flag_is_positional_independent() {
+        let args = ["minigrep", "duct", "poem.txt", "--regex"];
+        let config = Config::from_args(args.iter().map(|s| s.to_string())).unwrap();
+
+        assert_eq!("duct", config.query);
+        assert_eq!(vec!["poem.txt"], config.paths);
+        assert!(config.regex.is_some());
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        match build_regex("ERROR (\\d") {
+            Err(ConfigError::InvalidPattern(_)) => {}
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("expected an invalid pattern error"),
+        }
+    }
+
+    #[test]
+    fn run_returns_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "duct tape, duct tape\nRust\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "nothing here\n").unwrap();
+
+        let args = ["minigrep", "-c", "duct", dir.path().to_str().unwrap()];
+        let config = Config::from_args(args.iter().map(|s| s.to_string())).unwrap();
+        let stats = run(config).unwrap();
+
+        assert_eq!((2, 1), (stats.files_searched, stats.files_with_match));
+        assert_eq!((1, 2), (stats.matched_lines, stats.matches));
+        assert_eq!(39, stats.bytes_searched);
+        assert_eq!(dir.path().join("a.txt"), stats.per_file[0].0);
+    }
+
+    #[test]
+    fn run_reports_the_failed_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let found = dir.path().join("a.txt");
+        let missing = dir.path().join("missing.txt");
+        fs::write(&found, "duct tape\n").unwrap();
+
+        let args = ["minigrep", "-q", "duct", missing.to_str().unwrap()];
+        let config = Config::from_args(args.iter().map(|s| s.to_string())).unwrap();
+        match run(config) {
+            Err(err @ Error::Io { .. }) => assert_eq!(Some(missing.as_path()), err.path()),
+            other => panic!("expected an I/O error, got {:?}", other),
+        }
+
+        // -q stops at the first match, and then errors don't count.
+        let paths = [found.to_str().unwrap(), missing.to_str().unwrap()];
+        let args = ["minigrep", "-q", "duct", paths[0], paths[1]];
+        let config = Config::from_args(args.iter().map(|s| s.to_string())).unwrap();
+        let stats = run(config).unwrap();
+        assert_eq!((1, 1), (stats.files_searched, stats.matched_lines));
+    }
+
+    #[test]
+    fn run_counts_errors_below_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let corrupt = dir.path().join("b.gz");
+        fs::write(dir.path().join("a.txt"), "duct tape\n").unwrap();
+        fs::write(&corrupt, b"\x1f\x8b\x08\x00 not really gzip").unwrap();
+
+        let args = ["minigrep", "-z", "duct", dir.path().to_str().unwrap()];
+        let config = Config::from_args(args.iter().map(|s| s.to_string())).unwrap();
+        match run(config) {
+            Err(err) => assert_eq!(Some(corrupt.as_path()), err.path()),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn searches_only_read_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("a.txt"), "alpha\n").unwrap();
+
+        let args = ["minigrep", "-c", "duct tape", root];
+        run(Config::from_args(args.iter().map(|s| s.to_string())).unwrap()).unwrap();
+        assert!(!dir.path().join(INDEX_FILE).exists());
+
+        index(&[root.to_string()]).unwrap();
+        let saved = fs::read(dir.path().join(INDEX_FILE)).unwrap();
+        fs::write(dir.path().join("b.txt"), "duct tape\n").unwrap();
+
+        // The new file is found even though the index on disk predates it.
+        let stats = run(Config::from_args(args.iter().map(|s| s.to_string())).unwrap()).unwrap();
+        assert_eq!(1, stats.matched_lines);
+        assert_eq!(saved, fs::read(dir.path().join(INDEX_FILE)).unwrap());
+    }
+
+    #[test]
+    fn multiline_query() {
+        let query = "three.\nDuct";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        assert_eq!(
+            vec!["Pick three.\nDuct tape."],
+            search_multiline(query, contents)
+        );
+        assert!(search(query, contents).is_empty());
+    }
 }
@@ -0,0 +1,94 @@
+use std::ops::Range;
+
+use memchr::memmem::Finder;
+use memchr::{memchr, memrchr};
+
+/// Every line of `haystack` that contains `needle`, as the byte range of
+/// the line without its terminator.
+///
+/// Instead of splitting the input into lines and checking each one, this
+/// jumps from one occurrence of `needle` straight to the next with a SIMD
+/// substring search, and only looks for the line boundaries around the
+/// occurrences it finds. Text without a match is thereby skipped at close
+/// to memory speed.
+///
+/// `needle` must not be empty or contain a line break, since no line
+/// could match it then.
+pub struct MatchingLines<'h> {
+    haystack: &'h [u8],
+    finder: Finder<'static>,
+    pos: usize,
+}
+
+impl<'h> MatchingLines<'h> {
+    pub fn new(haystack: &'h [u8], needle: &[u8]) -> MatchingLines<'h> {
+        debug_assert!(is_searchable(needle), "unsearchable needle {:?}", needle);
+        MatchingLines {
+            haystack,
+            finder: Finder::new(needle).into_owned(),
+            pos: 0,
+        }
+    }
+}
+
+impl Iterator for MatchingLines<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        let haystack = self.haystack;
+        let hit = self.pos + self.finder.find(&haystack[self.pos..])?;
+        let start = memrchr(b'\n', &haystack[..hit]).map_or(0, |i| i + 1);
+        let end = memchr(b'\n', &haystack[hit..]).map_or(haystack.len(), |i| hit + i);
+        // The rest of this line needn't be searched again.
+        self.pos = (end + 1).min(haystack.len());
+
+        // Strip the terminator the same way `str::lines` does.
+        let terminated = end < haystack.len();
+        let text_end = if terminated && end > start && haystack[end - 1] == b'\r' {
+            end - 1
+        } else {
+            end
+        };
+        Some(start..text_end)
+    }
+}
+
+/// Whether [`MatchingLines`] can look for `needle`.
+pub fn is_searchable(needle: &[u8]) -> bool {
+    !needle.is_empty() && !needle.contains(&b'\n') && !needle.contains(&b'\r')
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn lines<'h>(haystack: &'h str, needle: &str) -> Vec<&'h str> {
+        MatchingLines::new(haystack.as_bytes(), needle.as_bytes())
+            .map(|line| &haystack[line])
+            .collect()
+    }
+
+    #[test]
+    fn finds_each_line_once() {
+        let haystack = "duct tape, duct tape\nRust\r\nno match\nduct";
+        assert_eq!(
+            vec!["duct tape, duct tape", "duct"],
+            lines(haystack, "duct")
+        );
+        assert_eq!(vec!["Rust"], lines(haystack, "st"));
+    }
+
+    #[test]
+    fn agrees_with_str_lines() {
+        let haystack = "a\r\n\r\nab\rc\nb a\na\r";
+        let expected: Vec<&str> = haystack.lines().filter(|l| l.contains('a')).collect();
+        assert_eq!(expected, lines(haystack, "a"));
+    }
+
+    #[test]
+    fn byte_ranges() {
+        let found: Vec<_> = MatchingLines::new(b"one\ntwo\nthree", b"t").collect();
+        assert_eq!(vec![4..7, 8..13], found);
+    }
+}
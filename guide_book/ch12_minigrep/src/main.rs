@@ -1,17 +1,43 @@
 use std::env;
 use std::process;
 
-use ch12_minigrep::Config;
+use ch12_minigrep::{Config, Error};
+
+// grep's exit statuses.
+const MATCHED: i32 = 0;
+const NO_MATCH: i32 = 1;
+const FAILED: i32 = 2;
 
 fn main() {
-    let config = Config::build(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
-    });
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "index") {
+        if let Err(e) = ch12_minigrep::index(&args[2..]) {
+            eprintln!("minigrep: {}", e);
+            process::exit(FAILED);
+        }
+        return;
+    }
 
-    if let Err(e) = ch12_minigrep::run(config) {
-        eprintln!("Application error: {}", e);
+    let config = Config::build(args.into_iter()).unwrap_or_else(|err| {
+        if err.is_informational() {
+            println!("{}", err);
+            process::exit(MATCHED);
+        }
+
+        match Error::from(err) {
+            Error::Usage(err) => {
+                eprintln!("Problem parsing arguments: {}", err);
+                eprintln!("Try 'minigrep --help' for more information.");
+            }
+            err => eprintln!("minigrep: {}", err),
+        }
+        process::exit(FAILED);
+    });
 
-        process::exit(1);
+    // Errors about the inputs have already been reported along the way.
+    match ch12_minigrep::run(config) {
+        Ok(stats) if stats.matched_lines > 0 => process::exit(MATCHED),
+        Ok(_) => process::exit(NO_MATCH),
+        Err(_) => process::exit(FAILED),
     }
 }
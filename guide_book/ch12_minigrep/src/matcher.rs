@@ -0,0 +1,203 @@
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::{FoldOptions, FoldedQuery, FuzzyQuery, Pattern, Query};
+
+/// Decides which lines match and where. Implement this to plug a custom
+/// matching strategy into [`Searcher`](crate::Searcher) or
+/// [`SearchStream`](crate::SearchStream).
+pub trait Matcher {
+    fn is_match(&self, line: &str) -> bool;
+
+    /// Byte ranges of every non-overlapping match within `line`.
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>>;
+
+    /// For approximate matchers, the edit distance of the closest match in
+    /// `line`; exact matchers have none.
+    fn distance(&self, _line: &str) -> Option<usize> {
+        None
+    }
+
+    /// The exact text of every match, for matchers that look for nothing
+    /// else. [`Searcher`](crate::Searcher) then skips from one occurrence
+    /// to the next with a SIMD substring search instead of checking each
+    /// line.
+    fn literal(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Matches an exact substring.
+#[derive(Clone, Debug)]
+pub struct Literal(String);
+
+impl Literal {
+    pub fn new(query: &str) -> Literal {
+        Literal(query.to_string())
+    }
+}
+
+impl Matcher for Literal {
+    fn is_match(&self, line: &str) -> bool {
+        line.contains(self.0.as_str())
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        line.match_indices(self.0.as_str())
+            .map(|(start, found)| start..start + found.len())
+            .collect()
+    }
+
+    fn literal(&self) -> Option<&str> {
+        Some(&self.0)
+    }
+}
+
+/// A case-insensitive literal, compared after full Unicode case folding.
+pub fn case_insensitive(query: &str) -> FoldedQuery {
+    let options = FoldOptions {
+        case_fold: true,
+        ..FoldOptions::default()
+    };
+    FoldedQuery::new(query, options)
+}
+
+impl Matcher for FoldedQuery {
+    fn is_match(&self, line: &str) -> bool {
+        FoldedQuery::is_match(self, line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        FoldedQuery::find_iter(self, line)
+    }
+}
+
+impl Matcher for Regex {
+    fn is_match(&self, line: &str) -> bool {
+        Regex::is_match(self, line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        Regex::find_iter(self, line).map(|m| m.range()).collect()
+    }
+}
+
+impl Matcher for FuzzyQuery {
+    fn is_match(&self, line: &str) -> bool {
+        FuzzyQuery::is_match(self, line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        FuzzyQuery::find_iter(self, line)
+            .into_iter()
+            .map(|m| m.range)
+            .collect()
+    }
+
+    fn distance(&self, line: &str) -> Option<usize> {
+        FuzzyQuery::distance(self, line)
+    }
+}
+
+impl Matcher for Query {
+    fn is_match(&self, line: &str) -> bool {
+        Query::is_match(self, line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        Query::find_iter(self, line)
+    }
+}
+
+impl Matcher for Pattern {
+    fn is_match(&self, line: &str) -> bool {
+        Pattern::is_match(self, line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        Pattern::find_iter(self, line)
+    }
+
+    fn distance(&self, line: &str) -> Option<usize> {
+        match self {
+            Pattern::Fuzzy(query) => query.distance(line),
+            _ => None,
+        }
+    }
+
+    fn literal(&self) -> Option<&str> {
+        match self {
+            Pattern::Literal(query) => Some(query),
+            _ => None,
+        }
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn is_match(&self, line: &str) -> bool {
+        (**self).is_match(line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        (**self).find_iter(line)
+    }
+
+    fn distance(&self, line: &str) -> Option<usize> {
+        (**self).distance(line)
+    }
+
+    fn literal(&self) -> Option<&str> {
+        (**self).literal()
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn is_match(&self, line: &str) -> bool {
+        (**self).is_match(line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        (**self).find_iter(line)
+    }
+
+    fn distance(&self, line: &str) -> Option<usize> {
+        (**self).distance(line)
+    }
+
+    fn literal(&self) -> Option<&str> {
+        (**self).literal()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    // Any matcher can be picked at runtime behind a trait object.
+    fn matchers() -> Vec<Box<dyn Matcher>> {
+        vec![
+            Box::new(Literal::new("Rust")),
+            Box::new(case_insensitive("RUST")),
+            Box::new(Regex::new(r"R\w+t").unwrap()),
+            Box::new(FuzzyQuery::new("Rast", 1, false)),
+        ]
+    }
+
+    #[test]
+    fn every_matcher_finds_the_same_word() {
+        for matcher in matchers() {
+            assert!(matcher.is_match("The Rust book"));
+            assert_eq!(vec![4..8], matcher.find_iter("The Rust book"));
+            assert!(!matcher.is_match("rest"));
+        }
+    }
+
+    #[test]
+    fn only_fuzzy_matchers_have_a_distance() {
+        let fuzzy = FuzzyQuery::new("Rast", 1, false);
+        assert_eq!(Some(1), Matcher::distance(&fuzzy, "The Rust book"));
+        assert_eq!(None, Literal::new("Rust").distance("The Rust book"));
+    }
+}
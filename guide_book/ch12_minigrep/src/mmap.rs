@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+/// Files at least this big are mapped into memory; smaller ones are
+/// cheaper to just read.
+const MMAP_THRESHOLD: u64 = 1 << 20;
+
+/// The whole contents of a file, for searching in one go.
+pub(crate) enum Contents {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Contents {
+    pub(crate) fn open(path: &Path) -> io::Result<Contents> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+
+        if metadata.is_file() && metadata.len() >= MMAP_THRESHOLD {
+            // SAFETY: the map is only ever read. Should another process
+            // truncate the file meanwhile, reading the lost pages raises
+            // SIGBUS; grep and ripgrep accept that risk for the speed, and
+            // --follow, being meant for files that change, never maps.
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                return Ok(Contents::Mapped(map));
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(metadata.len() as usize);
+        file.read_to_end(&mut bytes)?;
+        Ok(Contents::Read(bytes))
+    }
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(map) => map,
+            Contents::Read(bytes) => bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn maps_only_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.txt");
+        let large = dir.path().join("large.txt");
+        fs::write(&small, "duct tape\n").unwrap();
+        fs::write(&large, "x".repeat(MMAP_THRESHOLD as usize)).unwrap();
+
+        let contents = Contents::open(&small).unwrap();
+        assert!(matches!(contents, Contents::Read(_)));
+        assert_eq!(b"duct tape\n", &contents[..]);
+
+        let contents = Contents::open(&large).unwrap();
+        assert!(matches!(contents, Contents::Mapped(_)));
+        assert_eq!(MMAP_THRESHOLD as usize, contents.len());
+    }
+}
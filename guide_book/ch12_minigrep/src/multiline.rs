@@ -0,0 +1,105 @@
+use std::ops::Range;
+
+use memchr::{memchr, memrchr};
+
+use crate::matcher::Matcher;
+
+/// A run of whole lines covered by matches, for `-U` searches where a
+/// match can span line breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// The lines, as a byte range of the text without the last line's
+    /// terminator.
+    pub lines: Range<usize>,
+    /// Every match within the lines, as byte ranges of the text.
+    pub matches: Vec<Range<usize>>,
+}
+
+/// Matches `matcher` against the whole of `text` rather than line by
+/// line, and groups the matches into the lines they cover. Matches that
+/// share a line end up in the same block, so no line is reported twice.
+pub fn blocks(matcher: &impl Matcher, text: &str) -> Vec<Block> {
+    let bytes = text.as_bytes();
+    let mut blocks: Vec<Block> = Vec::new();
+
+    for found in matcher.find_iter(text) {
+        let start = memrchr(b'\n', &bytes[..found.start]).map_or(0, |i| i + 1);
+        // A match that ends with a line's `\n` doesn't reach into the next
+        // line; an empty one still covers the line it's on.
+        let last = if found.is_empty() {
+            found.start
+        } else {
+            found.end - 1
+        };
+        let end = memchr(b'\n', &bytes[last..]).map_or(bytes.len(), |i| last + i);
+
+        match blocks.last_mut() {
+            Some(block) if start <= block.lines.end => {
+                block.lines.end = block.lines.end.max(end);
+                block.matches.push(found);
+            }
+            _ => blocks.push(Block {
+                lines: start..end,
+                matches: vec![found],
+            }),
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+    use crate::matcher::Literal;
+    use regex::RegexBuilder;
+
+    const LOG: &str = "\
+start
+ERROR: failed
+  at main.rs:3
+  at lib.rs:9
+done
+ERROR: again
+  at main.rs:4";
+
+    fn lines<'t>(matcher: &impl Matcher, text: &'t str) -> Vec<&'t str> {
+        blocks(matcher, text)
+            .into_iter()
+            .map(|block| &text[block.lines])
+            .collect()
+    }
+
+    #[test]
+    fn literal_across_lines() {
+        let matcher = Literal::new("failed\n  at");
+        assert_eq!(vec!["ERROR: failed\n  at main.rs:3"], lines(&matcher, LOG));
+        assert_eq!(vec![13..24], blocks(&matcher, LOG)[0].matches);
+    }
+
+    #[test]
+    fn regex_across_lines() {
+        let regex = RegexBuilder::new(r"^ERROR.*\n(  at .*\n?)+")
+            .multi_line(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            vec![
+                "ERROR: failed\n  at main.rs:3\n  at lib.rs:9",
+                "ERROR: again\n  at main.rs:4"
+            ],
+            lines(&regex, LOG)
+        );
+    }
+
+    #[test]
+    fn matches_sharing_a_line_share_a_block() {
+        let matcher = Literal::new("rs:");
+        let found = blocks(&matcher, "a.rs:1 b.rs:2\nnone\nc.rs:3");
+        assert_eq!(2, found.len());
+        assert_eq!(0..13, found[0].lines);
+        assert_eq!(vec![2..5, 9..12], found[0].matches);
+        assert_eq!(19..25, found[1].lines);
+    }
+}
@@ -0,0 +1,285 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::json;
+use crate::printer::Printer;
+use crate::stats::FileStats;
+use crate::{Config, LineMatch, Pattern, Sink};
+
+/// A [`Sink`] that writes one of `run`'s output formats and keeps count
+/// of what it saw along the way.
+pub(crate) trait Output: Sink {
+    fn stats(&self) -> FileStats;
+}
+
+// What every output writes to, and its tally so far.
+struct Target<'a, W> {
+    path: &'a Path,
+    printer: Printer,
+    out: &'a mut W,
+    stats: FileStats,
+}
+
+/// Picks the output format for one input according to `config`.
+pub(crate) fn for_config<'a, W: Write>(
+    config: &'a Config,
+    pattern: &'a Pattern,
+    path: &'a Path,
+    printer: Printer,
+    binary: bool,
+    out: &'a mut W,
+) -> Box<dyn Output + 'a> {
+    let target = Target {
+        path,
+        printer,
+        out,
+        stats: FileStats::default(),
+    };
+
+    if config.quiet {
+        Box::new(Quiet { target })
+    } else if config.json {
+        Box::new(JsonLines {
+            target,
+            begun: false,
+            started: Instant::now(),
+        })
+    } else if config.files_with_matches || (binary && !config.count) {
+        Box::new(FirstMatch {
+            target,
+            list_path: config.files_with_matches,
+        })
+    } else if config.count {
+        Box::new(Count {
+            target,
+            // Lines that don't match have no matches to count.
+            matches: config.count_matches && !config.invert_match,
+        })
+    } else if config.fuzzy.is_some() {
+        Box::new(Ranked {
+            target,
+            ranked: Vec::new(),
+        })
+    } else {
+        Box::new(Standard {
+            target,
+            pattern,
+            replace: config.replace.as_deref(),
+        })
+    }
+}
+
+/// grep's usual output: matching lines (after `--replace`), context lines
+/// and `--` between groups.
+struct Standard<'a, W> {
+    target: Target<'a, W>,
+    pattern: &'a Pattern,
+    replace: Option<&'a str>,
+}
+
+impl<W: Write> Sink for Standard<'_, W> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        let t = &mut self.target;
+        t.stats.record(found);
+
+        match self.replace {
+            Some(template) => {
+                let (line, matches) = self.pattern.replace(&found.line, template);
+                let found = LineMatch {
+                    line,
+                    matches,
+                    ..found.clone()
+                };
+                t.printer.line(t.out, t.path, &found, ':')?;
+            }
+            None => t.printer.line(t.out, t.path, found, ':')?,
+        }
+        Ok(true)
+    }
+
+    fn context(&mut self, line: &LineMatch) -> io::Result<bool> {
+        let t = &mut self.target;
+        t.printer.line(t.out, t.path, line, '-')?;
+        Ok(true)
+    }
+
+    fn context_break(&mut self) -> io::Result<bool> {
+        self.target.printer.context_break(self.target.out)?;
+        Ok(true)
+    }
+
+    fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+        self.target.stats.bytes_searched = bytes_read;
+        Ok(())
+    }
+}
+
+/// `--json`: `begin`, `match`/`context` and `end` events for each input
+/// that had a match.
+struct JsonLines<'a, W> {
+    target: Target<'a, W>,
+    begun: bool,
+    started: Instant,
+}
+
+impl<W: Write> JsonLines<'_, W> {
+    fn line(&mut self, kind: &str, found: &LineMatch) -> io::Result<bool> {
+        let t = &mut self.target;
+        if !self.begun {
+            json::begin(t.out, t.path)?;
+            self.begun = true;
+        }
+        json::line(t.out, kind, t.path, found)?;
+        Ok(true)
+    }
+}
+
+impl<W: Write> Sink for JsonLines<'_, W> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        self.target.stats.record(found);
+        self.line("match", found)
+    }
+
+    fn context(&mut self, line: &LineMatch) -> io::Result<bool> {
+        self.line("context", line)
+    }
+
+    fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+        let t = &mut self.target;
+        t.stats.bytes_searched = bytes_read;
+        if self.begun {
+            json::end(t.out, t.path, t.stats, self.started.elapsed())?;
+        }
+        Ok(())
+    }
+}
+
+/// `-l`, and binary files: one match is enough to say so, and the rest of
+/// the input isn't even read.
+struct FirstMatch<'a, W> {
+    target: Target<'a, W>,
+    list_path: bool,
+}
+
+impl<W: Write> Sink for FirstMatch<'_, W> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        let t = &mut self.target;
+        t.stats.record(found);
+        if self.list_path {
+            t.printer.path(t.out, t.path)?;
+        } else {
+            // The lines themselves would only be garbage on a terminal.
+            t.printer.binary_matches(t.out, t.path)?;
+        }
+        Ok(false)
+    }
+
+    fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+        self.target.stats.bytes_searched = bytes_read;
+        Ok(())
+    }
+}
+
+/// `-q`: nothing at all, and the first match ends the search.
+struct Quiet<'a, W> {
+    target: Target<'a, W>,
+}
+
+impl<W: Write> Sink for Quiet<'_, W> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        self.target.stats.record(found);
+        Ok(false)
+    }
+
+    fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+        self.target.stats.bytes_searched = bytes_read;
+        Ok(())
+    }
+}
+
+/// `-c`: just the number of matching lines, or of matches with
+/// `--count-matches`.
+struct Count<'a, W> {
+    target: Target<'a, W>,
+    matches: bool,
+}
+
+impl<W: Write> Sink for Count<'_, W> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        self.target.stats.record(found);
+        Ok(true)
+    }
+
+    fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+        let t = &mut self.target;
+        t.stats.bytes_searched = bytes_read;
+        let count = if self.matches {
+            t.stats.matches
+        } else {
+            t.stats.matched_lines
+        };
+        t.printer.count(t.out, t.path, count)
+    }
+}
+
+/// `--fuzzy`: matching lines, closest first. The sort is stable, so ties
+/// keep their line order.
+struct Ranked<'a, W> {
+    target: Target<'a, W>,
+    ranked: Vec<LineMatch>,
+}
+
+impl<W: Write> Sink for Ranked<'_, W> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        self.target.stats.record(found);
+        self.ranked.push(found.clone());
+        Ok(true)
+    }
+
+    fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+        let t = &mut self.target;
+        t.stats.bytes_searched = bytes_read;
+        self.ranked.sort_by_key(|found| found.distance);
+        for found in &self.ranked {
+            t.printer.line(t.out, t.path, found, ':')?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Output for Standard<'_, W> {
+    fn stats(&self) -> FileStats {
+        self.target.stats
+    }
+}
+
+impl<W: Write> Output for JsonLines<'_, W> {
+    fn stats(&self) -> FileStats {
+        self.target.stats
+    }
+}
+
+impl<W: Write> Output for FirstMatch<'_, W> {
+    fn stats(&self) -> FileStats {
+        self.target.stats
+    }
+}
+
+impl<W: Write> Output for Quiet<'_, W> {
+    fn stats(&self) -> FileStats {
+        self.target.stats
+    }
+}
+
+impl<W: Write> Output for Count<'_, W> {
+    fn stats(&self) -> FileStats {
+        self.target.stats
+    }
+}
+
+impl<W: Write> Output for Ranked<'_, W> {
+    fn stats(&self) -> FileStats {
+        self.target.stats
+    }
+}
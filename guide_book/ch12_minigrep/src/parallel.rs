@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+
+use hello::ThreadPool;
+
+/// Runs `job` on every item using a pool of `threads` workers, handing
+/// each result to `report` in the order of `items`, not completion order.
+///
+/// A result is reported as soon as all results before it are in, so a
+/// slow item only holds back the output that comes after it. If a job
+/// panics, the other results are still reported, and the first lost one
+/// is returned as an error.
+pub fn ordered_map<T, R, F>(
+    threads: usize,
+    items: Vec<T>,
+    job: F,
+    mut report: impl FnMut(R),
+) -> Result<(), LostJob>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+{
+    let pool = ThreadPool::silent(threads);
+    let job = Arc::new(job);
+    let (sender, receiver) = mpsc::channel();
+    let total = items.len();
+
+    for (index, item) in items.into_iter().enumerate() {
+        let job = Arc::clone(&job);
+        let sender = sender.clone();
+        pool.execute(move || {
+            // Caught so that the worker survives to be joined.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| job(item))).ok();
+            // The receiver only goes away if `report` panicked.
+            let _ = sender.send((index, result));
+        });
+    }
+    drop(sender);
+
+    let mut finished = BTreeMap::new();
+    let mut next = 0;
+    let mut lost = None;
+
+    for (index, result) in receiver {
+        finished.insert(index, result);
+        while let Some(result) = finished.remove(&next) {
+            match result {
+                Some(result) => report(result),
+                None => {
+                    lost.get_or_insert(LostJob { index: next });
+                }
+            }
+            next += 1;
+        }
+    }
+    drop(pool);
+
+    if next < total {
+        lost.get_or_insert(LostJob { index: next });
+    }
+    lost.map_or(Ok(()), Err)
+}
+
+/// A job of [`ordered_map`] that panicked instead of returning a result.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LostJob {
+    /// The job's position among the items.
+    pub index: usize,
+}
+
+impl fmt::Display for LostJob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "job {} panicked", self.index)
+    }
+}
+
+impl std::error::Error for LostJob {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn reports_in_input_order() {
+        let mut seen = Vec::new();
+
+        // Earlier items take longer, so they finish last.
+        ordered_map(
+            4,
+            (0..8u64).collect(),
+            |n| {
+                thread::sleep(Duration::from_millis(5 * (8 - n)));
+                n * 10
+            },
+            |result| seen.push(result),
+        )
+        .unwrap();
+
+        assert_eq!(vec![0, 10, 20, 30, 40, 50, 60, 70], seen);
+    }
+
+    #[test]
+    fn reports_a_panicking_job() {
+        let mut seen = Vec::new();
+
+        let result = ordered_map(
+            2,
+            (0..4u64).collect(),
+            |n| {
+                assert_ne!(n, 1, "job 1 fails");
+                n
+            },
+            |result| seen.push(result),
+        );
+
+        assert_eq!(Err(LostJob { index: 1 }), result);
+        assert_eq!(vec![0, 2, 3], seen);
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::LineMatch;
+
+// The same palette GNU grep uses by default.
+const MATCH: &str = "\x1b[1;31m";
+const PATH: &str = "\x1b[35m";
+const LINE_NUMBER: &str = "\x1b[32m";
+const SEPARATOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Formats search output the way grep does: `path:line:text` for matches,
+/// `path-line-text` for context lines, optionally with ANSI colors.
+/// Fuzzy matches also get their edit distance: `path:line:~1:text`.
+#[derive(Clone, Copy)]
+pub(crate) struct Printer {
+    pub(crate) show_path: bool,
+    pub(crate) line_number: bool,
+    pub(crate) color: bool,
+}
+
+impl Printer {
+    /// For `-l`: just the name of a file that matched.
+    pub(crate) fn path(&self, out: &mut impl Write, path: &Path) -> io::Result<()> {
+        self.paint(out, PATH, &path.display().to_string())?;
+        writeln!(out)
+    }
+
+    /// Stands in for the matching lines of a binary file.
+    pub(crate) fn binary_matches(&self, out: &mut impl Write, path: &Path) -> io::Result<()> {
+        writeln!(out, "Binary file {} matches", path.display())
+    }
+
+    /// For `-c`: the number of matching lines in a file.
+    pub(crate) fn count(&self, out: &mut impl Write, path: &Path, count: u64) -> io::Result<()> {
+        if self.show_path {
+            self.paint(out, PATH, &path.display().to_string())?;
+            self.paint(out, SEPARATOR, ":")?;
+        }
+        writeln!(out, "{}", count)
+    }
+
+    /// The `--` between groups of context lines.
+    pub(crate) fn context_break(&self, out: &mut impl Write) -> io::Result<()> {
+        self.paint(out, SEPARATOR, "--")?;
+        writeln!(out)
+    }
+
+    /// `separator` is `:` for a matching line and `-` for a context line.
+    /// A match spanning several lines (with `-U`, or a multi-line CSV
+    /// record) gets each of them printed with its own prefix.
+    pub(crate) fn line(
+        &self,
+        out: &mut impl Write,
+        path: &Path,
+        found: &LineMatch,
+        separator: char,
+    ) -> io::Result<()> {
+        let separator = separator.to_string();
+        let mut start = 0;
+
+        for (i, line) in found.line.split('\n').enumerate() {
+            if self.show_path {
+                self.paint(out, PATH, &path.display().to_string())?;
+                self.paint(out, SEPARATOR, &separator)?;
+            }
+            if self.line_number {
+                self.paint(out, LINE_NUMBER, &(found.line_number + i).to_string())?;
+                self.paint(out, SEPARATOR, &separator)?;
+            }
+            if let (0, Some(distance)) = (i, found.distance) {
+                self.paint(out, LINE_NUMBER, &format!("~{}", distance))?;
+                self.paint(out, SEPARATOR, &separator)?;
+            }
+
+            let end = start + line.len();
+            self.text(out, &found.line[start..end], &found.matches, start)?;
+            start = end + 1;
+        }
+        Ok(())
+    }
+
+    // Writes one line that starts at `offset` in the text `matches` refer
+    // to, highlighting the parts of them within it.
+    fn text(
+        &self,
+        out: &mut impl Write,
+        line: &str,
+        matches: &[Range<usize>],
+        offset: usize,
+    ) -> io::Result<()> {
+        if !self.color {
+            return writeln!(out, "{}", line);
+        }
+
+        let mut written = 0;
+        for range in matches {
+            let start = range.start.saturating_sub(offset);
+            let end = range.end.saturating_sub(offset).min(line.len());
+            // Empty matches (e.g. an empty query) have nothing to highlight.
+            if start >= end || start < written {
+                continue;
+            }
+            write!(out, "{}", &line[written..start])?;
+            self.paint(out, MATCH, &line[start..end])?;
+            written = end;
+        }
+        writeln!(out, "{}", &line[written..])
+    }
+
+    fn paint(&self, out: &mut impl Write, color: &str, text: &str) -> io::Result<()> {
+        if self.color {
+            write!(out, "{}{}{}", color, text, RESET)
+        } else {
+            write!(out, "{}", text)
+        }
+    }
+}
+
+#[cfg(test)]
+// `vec![0..5]` is a list holding one range here, not a mistake.
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn render(printer: Printer, found: &LineMatch) -> String {
+        let mut out = Vec::new();
+        printer
+            .line(&mut out, Path::new("log.txt"), found, ':')
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plain_output() {
+        let printer = Printer {
+            show_path: true,
+            line_number: true,
+            color: false,
+        };
+        let found = LineMatch {
+            line_number: 7,
+            byte_offset: 120,
+            line: "ERROR 503".to_string(),
+            matches: vec![0..5],
+            distance: None,
+        };
+
+        assert_eq!("log.txt:7:ERROR 503\n", render(printer, &found));
+    }
+
+    #[test]
+    fn highlights_every_match() {
+        let printer = Printer {
+            show_path: false,
+            line_number: false,
+            color: true,
+        };
+        let found = LineMatch {
+            line_number: 1,
+            byte_offset: 0,
+            line: "a-b-a".to_string(),
+            matches: vec![0..1, 4..5],
+            distance: None,
+        };
+
+        assert_eq!(
+            "\x1b[1;31ma\x1b[0m-b-\x1b[1;31ma\x1b[0m\n",
+            render(printer, &found)
+        );
+    }
+
+    #[test]
+    fn fuzzy_distance() {
+        let printer = Printer {
+            show_path: false,
+            line_number: true,
+            color: false,
+        };
+        let found = LineMatch {
+            line_number: 2,
+            byte_offset: 10,
+            line: "conection reset".to_string(),
+            matches: vec![0..9],
+            distance: Some(1),
+        };
+
+        assert_eq!("2:~1:conection reset\n", render(printer, &found));
+    }
+
+    #[test]
+    fn spanning_several_lines() {
+        let printer = Printer {
+            show_path: true,
+            line_number: true,
+            color: true,
+        };
+        let found = LineMatch {
+            line_number: 7,
+            byte_offset: 60,
+            line: "ERROR: failed\n  at main.rs".to_string(),
+            matches: vec![7..18],
+            distance: None,
+        };
+
+        let expected = format!(
+            "{p}log.txt{r}{s}:{r}{n}7{r}{s}:{r}ERROR: {m}failed{r}\n\
+             {p}log.txt{r}{s}:{r}{n}8{r}{s}:{r}{m}  at{r} main.rs\n",
+            p = PATH,
+            s = SEPARATOR,
+            n = LINE_NUMBER,
+            m = MATCH,
+            r = RESET,
+        );
+        assert_eq!(expected, render(printer, &found));
+    }
+}
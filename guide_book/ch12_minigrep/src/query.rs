@@ -0,0 +1,487 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use aho_corasick::AhoCorasick;
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+
+use crate::fold::{self, FoldOptions};
+
+/// How the terms of a [`Query`] are interpreted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QueryOptions {
+    /// Terms are regular expressions rather than literal strings.
+    pub regex: bool,
+    pub ignore_case: bool,
+    /// Regex `^` and `$` match at line breaks too, for `-U` searches.
+    pub multi_line: bool,
+    /// Folding and normalization for literal terms; `case_fold` is
+    /// expected to mirror `ignore_case`.
+    pub fold: FoldOptions,
+}
+
+#[derive(Debug)]
+pub enum QueryError {
+    /// The boolean expression couldn't be parsed.
+    Syntax(String),
+    Regex(regex::Error),
+    Automaton(aho_corasick::BuildError),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::Syntax(message) => write!(f, "{}", message),
+            QueryError::Regex(err) => write!(f, "{}", err),
+            QueryError::Automaton(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for QueryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QueryError::Syntax(_) => None,
+            QueryError::Regex(err) => Some(err),
+            QueryError::Automaton(err) => Some(err),
+        }
+    }
+}
+
+/// A boolean combination of terms, each referring to a term by index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Term(usize),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, present: &[bool]) -> bool {
+        match self {
+            Expr::Term(id) => present[*id],
+            Expr::Not(inner) => !inner.eval(present),
+            Expr::And(left, right) => left.eval(present) && right.eval(present),
+            Expr::Or(left, right) => left.eval(present) || right.eval(present),
+        }
+    }
+
+    // Marks the terms that can make a line match by being present, as
+    // opposed to those only ever used under a NOT.
+    fn mark_positive(&self, negated: bool, positive: &mut [bool]) {
+        match self {
+            Expr::Term(id) => positive[*id] |= !negated,
+            Expr::Not(inner) => inner.mark_positive(!negated, positive),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.mark_positive(negated, positive);
+                right.mark_positive(negated, positive);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Terms {
+    /// Every literal term compiled into one Aho-Corasick automaton, so a
+    /// line is scanned once no matter how many terms there are.
+    Literal {
+        automaton: AhoCorasick,
+        fold: Option<FoldOptions>,
+    },
+    Regex {
+        set: RegexSet,
+        // Individual regexes, only needed to report match ranges.
+        regexes: Vec<Regex>,
+    },
+}
+
+/// Several patterns searched for at once, combined by a boolean [`Expr`].
+#[derive(Clone, Debug)]
+pub struct Query {
+    terms: Terms,
+    expr: Expr,
+    positive: Vec<bool>,
+}
+
+impl Query {
+    /// Matches lines containing at least one of `patterns`.
+    pub fn any(patterns: &[String], options: QueryOptions) -> Result<Query, QueryError> {
+        let expr = (0..patterns.len())
+            .map(Expr::Term)
+            .reduce(|left, right| Expr::Or(Box::new(left), Box::new(right)))
+            .ok_or_else(|| QueryError::Syntax("no patterns given".to_string()))?;
+
+        Query::new(patterns.to_vec(), expr, options)
+    }
+
+    /// Parses an expression such as `foo AND NOT (bar OR "two words")`.
+    /// `AND`, `OR` and `NOT` must be upper case; terms next to each other
+    /// without an operator are ANDed.
+    pub fn parse(expression: &str, options: QueryOptions) -> Result<Query, QueryError> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            terms: Vec::new(),
+        };
+
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(QueryError::Syntax(format!("unexpected {}", token)));
+        }
+
+        Query::new(parser.terms, expr, options)
+    }
+
+    fn new(terms: Vec<String>, expr: Expr, options: QueryOptions) -> Result<Query, QueryError> {
+        let mut positive = vec![false; terms.len()];
+        expr.mark_positive(false, &mut positive);
+
+        let terms = if options.regex {
+            let set = RegexSetBuilder::new(&terms)
+                .case_insensitive(options.ignore_case)
+                .multi_line(options.multi_line)
+                .build()
+                .map_err(QueryError::Regex)?;
+            let regexes = terms
+                .iter()
+                .map(|term| {
+                    RegexBuilder::new(term)
+                        .case_insensitive(options.ignore_case)
+                        .multi_line(options.multi_line)
+                        .build()
+                })
+                .collect::<Result<_, _>>()
+                .map_err(QueryError::Regex)?;
+            Terms::Regex { set, regexes }
+        } else {
+            let folds = options.fold != FoldOptions::default();
+            let fold = folds.then_some(options.fold);
+            let needles: Vec<String> = match fold {
+                Some(fold) => terms.iter().map(|t| fold::fold_text(t, fold)).collect(),
+                None => terms,
+            };
+            let automaton = AhoCorasick::new(needles).map_err(QueryError::Automaton)?;
+            Terms::Literal { automaton, fold }
+        };
+
+        Ok(Query {
+            terms,
+            expr,
+            positive,
+        })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.expr.eval(&self.present(line))
+    }
+
+    /// Ranges of the terms that made the line match, merged where they
+    /// overlap. Terms that only appear under a NOT are never reported.
+    pub fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+
+        match &self.terms {
+            Terms::Literal { automaton, fold } => {
+                let (haystack, origins) = match fold {
+                    Some(fold) => {
+                        let (folded, origins) = fold::fold(line, *fold);
+                        (folded, Some(origins))
+                    }
+                    None => (line.to_string(), None),
+                };
+                for found in automaton.find_overlapping_iter(&haystack) {
+                    if !self.positive[found.pattern().as_usize()] || found.is_empty() {
+                        continue;
+                    }
+                    ranges.push(match &origins {
+                        Some(origins) => origins[found.start()].start..origins[found.end() - 1].end,
+                        None => found.range(),
+                    });
+                }
+            }
+            Terms::Regex { set, regexes } => {
+                for id in set.matches(line).iter() {
+                    if self.positive[id] {
+                        ranges.extend(regexes[id].find_iter(line).map(|m| m.range()));
+                    }
+                }
+            }
+        }
+
+        merge(ranges)
+    }
+
+    // Which terms occur anywhere in `line`.
+    fn present(&self, line: &str) -> Vec<bool> {
+        let mut present = vec![false; self.positive.len()];
+
+        match &self.terms {
+            Terms::Literal { automaton, fold } => {
+                let folded;
+                let haystack = match fold {
+                    Some(fold) => {
+                        folded = fold::fold_text(line, *fold);
+                        folded.as_str()
+                    }
+                    None => line,
+                };
+                for found in automaton.find_overlapping_iter(haystack) {
+                    present[found.pattern().as_usize()] = true;
+                }
+            }
+            Terms::Regex { set, .. } => {
+                for id in set.matches(line).iter() {
+                    present[id] = true;
+                }
+            }
+        }
+
+        present
+    }
+}
+
+fn merge(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| (range.start, range.end));
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Term(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Term(term) => write!(f, "term \"{}\"", term),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let mut term = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => term.extend(chars.next()),
+                        Some(c) => term.push(c),
+                        None => return Err(QueryError::Syntax("unterminated quote".to_string())),
+                    }
+                }
+                tokens.push(Token::Term(term));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Term(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Recursive descent, loosest binding first: OR, then AND, then NOT.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    terms: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.position += 1,
+                // Implicit AND between adjacent operands.
+                Some(Token::Not | Token::Open | Token::Term(_)) => {}
+                _ => return Ok(expr),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, QueryError> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+
+        match token {
+            Some(Token::Term(term)) => {
+                let term = term.clone();
+                // Repeated terms share one slot in the automaton.
+                let id = match self.terms.iter().position(|t| *t == term) {
+                    Some(id) => id,
+                    None => {
+                        self.terms.push(term);
+                        self.terms.len() - 1
+                    }
+                };
+                Ok(Expr::Term(id))
+            }
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(QueryError::Syntax("missing ')'".to_string()));
+                }
+                self.position += 1;
+                Ok(expr)
+            }
+            Some(token) => Err(QueryError::Syntax(format!("unexpected {}", token))),
+            None => Err(QueryError::Syntax("unexpected end of query".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn literal() -> QueryOptions {
+        QueryOptions::default()
+    }
+
+    #[test]
+    fn any_of_several_literals() {
+        let patterns = vec!["foo".to_string(), "bar".to_string(), "oba".to_string()];
+        let query = Query::any(&patterns, literal()).unwrap();
+
+        assert!(query.is_match("xx bar"));
+        assert!(!query.is_match("baz"));
+        // Overlapping matches are merged into one highlighted range.
+        assert_eq!(vec![0..6], query.find_iter("foobar"));
+    }
+
+    #[test]
+    fn boolean_expression() {
+        let query = Query::parse("ERROR AND NOT (timeout OR \"retry later\")", literal()).unwrap();
+
+        assert!(query.is_match("ERROR disk full"));
+        assert!(!query.is_match("ERROR timeout"));
+        assert!(!query.is_match("ERROR, retry later"));
+        assert!(!query.is_match("WARN disk full"));
+        // Negated terms are never highlighted.
+        assert_eq!(vec![0..5], query.find_iter("ERROR disk full"));
+    }
+
+    #[test]
+    fn implicit_and_and_precedence() {
+        let query = Query::parse("a b OR c", literal()).unwrap();
+        assert!(query.is_match("b a"));
+        assert!(query.is_match("c"));
+        assert!(!query.is_match("a"));
+
+        let query = Query::parse("NOT a OR b", literal()).unwrap();
+        assert!(query.is_match("x"));
+        assert!(query.is_match("ab"));
+        assert!(!query.is_match("a"));
+    }
+
+    #[test]
+    fn case_insensitive_terms_are_folded() {
+        let options = QueryOptions {
+            ignore_case: true,
+            fold: FoldOptions {
+                case_fold: true,
+                ..FoldOptions::default()
+            },
+            ..literal()
+        };
+        let query = Query::parse("straße AND NOT closed", options).unwrap();
+
+        assert!(query.is_match("STRASSE open"));
+        assert!(!query.is_match("Strasse CLOSED"));
+        assert_eq!(vec![4..11], query.find_iter("Die STRASSE"));
+    }
+
+    #[test]
+    fn regex_terms() {
+        let options = QueryOptions {
+            regex: true,
+            ..literal()
+        };
+        let query = Query::parse(r"ERROR\s\d{3} AND NOT ^DEBUG", options).unwrap();
+
+        assert!(query.is_match("ERROR 503 upstream"));
+        assert!(!query.is_match("DEBUG ERROR 503"));
+        assert_eq!(vec![0..9], query.find_iter("ERROR 503"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for bad in ["", "a AND", "(a OR b", "a )", "\"open"] {
+            assert!(
+                matches!(Query::parse(bad, literal()), Err(QueryError::Syntax(_))),
+                "{:?} should not parse",
+                bad
+            );
+        }
+    }
+}
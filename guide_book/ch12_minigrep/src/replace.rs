@@ -0,0 +1,148 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use tempfile::NamedTempFile;
+
+use crate::stats::FileStats;
+use crate::{walk, Pattern};
+
+/// Rewrites every line of `path` that `pattern` matches, leaving all other
+/// lines byte for byte as they were, including line endings and any bytes
+/// that aren't valid UTF-8.
+///
+/// The new contents go to a temporary file next to `path` which is then
+/// renamed over it, so readers see either the old file or the new one and
+/// never half of each. Files without a match (and binary files) aren't
+/// touched at all. If `backup_suffix` is given, the original is kept
+/// alongside as `path` + suffix.
+pub(crate) fn in_place(
+    path: &Path,
+    pattern: &Pattern,
+    template: &str,
+    backup_suffix: Option<&str>,
+) -> io::Result<FileStats> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut stats = FileStats::default();
+    if walk::is_binary(reader.fill_buf()?) {
+        return Ok(stats);
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut out = BufWriter::new(NamedTempFile::new_in(dir)?);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        stats.bytes_searched += read as u64;
+
+        let end = buf.len() - terminator_len(&buf);
+        let line = match std::str::from_utf8(&buf[..end]) {
+            Ok(line) if pattern.is_match(line) => line,
+            _ => {
+                out.write_all(&buf)?;
+                continue;
+            }
+        };
+
+        let (replaced, ranges) = pattern.replace(line, template);
+        stats.matched_lines += 1;
+        stats.matches += ranges.len() as u64;
+        out.write_all(replaced.as_bytes())?;
+        out.write_all(&buf[end..])?;
+    }
+
+    if stats.matched_lines == 0 {
+        return Ok(stats);
+    }
+    // Flushes what's left, so a failed write can't go unnoticed.
+    let temp = out.into_inner().map_err(|err| err.into_error())?;
+
+    temp.as_file()
+        .set_permissions(fs::metadata(path)?.permissions())?;
+    if let Some(suffix) = backup_suffix {
+        let backup = backup_path(path, suffix);
+        // A hard link keeps the original in place until the rename below;
+        // fall back to copying on file systems without them.
+        let _ = fs::remove_file(&backup);
+        if fs::hard_link(path, &backup).is_err() {
+            fs::copy(path, &backup)?;
+        }
+    }
+    temp.persist(path).map_err(|err| err.error)?;
+
+    Ok(stats)
+}
+
+fn terminator_len(line: &[u8]) -> usize {
+    if line.ends_with(b"\r\n") {
+        2
+    } else if line.ends_with(b"\n") {
+        1
+    } else {
+        0
+    }
+}
+
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(suffix);
+    PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn rewrites_matching_lines_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, b"port=80\r\nhost=\xff\nport=8080").unwrap();
+
+        let pattern = Pattern::Regex(Regex::new(r"port=(\d+)").unwrap());
+        let stats = in_place(&path, &pattern, "port=${1}0", None).unwrap();
+
+        assert_eq!(2, stats.matched_lines);
+        assert_eq!(
+            b"port=800\r\nhost=\xff\nport=80800".to_vec(),
+            fs::read(&path).unwrap()
+        );
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn keeps_a_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "colour\n").unwrap();
+
+        in_place(&path, &Pattern::literal("colour"), "color", Some(".bak")).unwrap();
+
+        assert_eq!("color\n", fs::read_to_string(&path).unwrap());
+        let backup = dir.path().join("notes.txt.bak");
+        assert_eq!("colour\n", fs::read_to_string(backup).unwrap());
+    }
+
+    #[test]
+    fn leaves_files_without_matches_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "nothing here\n").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let stats = in_place(&path, &Pattern::literal("x"), "y", Some(".bak")).unwrap();
+
+        assert_eq!(0, stats.matched_lines);
+        assert_eq!(modified, fs::metadata(&path).unwrap().modified().unwrap());
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+}
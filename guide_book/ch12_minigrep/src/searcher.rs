@@ -0,0 +1,378 @@
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use memchr::{memchr, memchr_iter};
+
+use crate::literal::{self, MatchingLines};
+use crate::matcher::Matcher;
+use crate::mmap::Contents;
+use crate::multiline;
+use crate::stream::Line;
+use crate::{LineMatch, SearchResult, SearchStream};
+
+/// Receives the results of a [`Searcher`]. Every callback but `finish`
+/// returns whether to keep going, so a sink can stop a search early, the
+/// way `-l` only needs the first match of a file.
+pub trait Sink {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool>;
+
+    /// A non-matching line near a match, when context was asked for.
+    fn context(&mut self, _line: &LineMatch) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// The lines before and after this aren't adjacent.
+    fn context_break(&mut self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Called once the search ends, with how much input it read.
+    fn finish(&mut self, _bytes_read: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects every matching line, ignoring context.
+impl Sink for Vec<LineMatch> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        self.push(found.clone());
+        Ok(true)
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+        (**self).matched(found)
+    }
+
+    fn context(&mut self, line: &LineMatch) -> io::Result<bool> {
+        (**self).context(line)
+    }
+
+    fn context_break(&mut self) -> io::Result<bool> {
+        (**self).context_break()
+    }
+
+    fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+        (**self).finish(bytes_read)
+    }
+}
+
+/// Runs any [`Matcher`] over any input, line by line, handing the
+/// results to a [`Sink`].
+///
+/// ```
+/// use ch12_minigrep::{matcher::Literal, LineMatch, Searcher};
+///
+/// let mut found: Vec<LineMatch> = Vec::new();
+/// Searcher::new()
+///     .before_context(1)
+///     .search_reader(Literal::new("tape"), "Pick three.\nDuct tape.".as_bytes(), &mut found)
+///     .unwrap();
+/// assert_eq!(2, found[0].line_number);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Searcher {
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    multi_line: bool,
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher::default()
+    }
+
+    /// Reports the lines that do *not* match instead.
+    pub fn invert_match(mut self, yes: bool) -> Searcher {
+        self.invert_match = yes;
+        self
+    }
+
+    pub fn before_context(mut self, lines: usize) -> Searcher {
+        self.before_context = lines;
+        self
+    }
+
+    pub fn after_context(mut self, lines: usize) -> Searcher {
+        self.after_context = lines;
+        self
+    }
+
+    /// Matches against the whole input at once, so matches can span lines;
+    /// each run of lines they cover is reported as one [`LineMatch`].
+    /// Inverting and context don't apply then, and the input is read into
+    /// memory first.
+    pub fn multi_line(mut self, yes: bool) -> Searcher {
+        self.multi_line = yes;
+        self
+    }
+
+    pub fn search_reader(
+        &self,
+        matcher: impl Matcher,
+        mut reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> io::Result<()> {
+        if self.multi_line {
+            let mut contents = Vec::new();
+            reader.read_to_end(&mut contents)?;
+            return self.search_multi_line(&matcher, &contents, sink);
+        }
+
+        let mut results = SearchStream::new(reader, matcher)
+            .invert_match(self.invert_match)
+            .with_context(self.before_context, self.after_context);
+
+        for result in results.by_ref() {
+            let more = match result? {
+                SearchResult::Match(found) => sink.matched(&found)?,
+                SearchResult::Context(line) => sink.context(&line)?,
+                SearchResult::Break => sink.context_break()?,
+            };
+            if !more {
+                break;
+            }
+        }
+
+        sink.finish(results.bytes_read())
+    }
+
+    /// Searches the file at `path` in one go, memory-mapping it if it's
+    /// large.
+    pub fn search_path(
+        &self,
+        matcher: impl Matcher,
+        path: &Path,
+        sink: &mut impl Sink,
+    ) -> io::Result<()> {
+        let contents = Contents::open(path)?;
+        self.search_slice(matcher, &contents, sink)
+    }
+
+    /// Searches input that's entirely in memory. For a literal matcher,
+    /// this only looks at the lines around its occurrences.
+    pub fn search_slice(
+        &self,
+        matcher: impl Matcher,
+        slice: &[u8],
+        sink: &mut impl Sink,
+    ) -> io::Result<()> {
+        if self.multi_line {
+            return self.search_multi_line(&matcher, slice, sink);
+        }
+
+        // Context and inverted searches need every line looked at anyway.
+        let every_line = self.invert_match || self.before_context > 0 || self.after_context > 0;
+        // Invalid UTF-8 only turns into U+FFFD once lines are repaired, so
+        // it can't be found in the raw bytes.
+        let raw = |needle: &str| {
+            literal::is_searchable(needle.as_bytes()) && !needle.contains('\u{FFFD}')
+        };
+        match matcher.literal() {
+            Some(needle) if !every_line && raw(needle) => {
+                self.search_literal(&matcher, needle, slice, sink)
+            }
+            _ => self.search_reader(matcher, slice, sink),
+        }
+    }
+
+    fn search_literal(
+        &self,
+        matcher: &impl Matcher,
+        needle: &str,
+        slice: &[u8],
+        sink: &mut impl Sink,
+    ) -> io::Result<()> {
+        let mut number = 1;
+        let mut counted = 0;
+        let mut bytes_read = slice.len();
+
+        for range in MatchingLines::new(slice, needle.as_bytes()) {
+            // Lines are only counted up to where they're needed.
+            number += memchr_iter(b'\n', &slice[counted..range.start]).count();
+            counted = range.start;
+
+            let text = String::from_utf8_lossy(&slice[range.clone()]);
+            let line = Line {
+                number,
+                offset: range.start as u64,
+                text: &text,
+            };
+            if !sink.matched(&LineMatch::new(&line, matcher, true))? {
+                bytes_read =
+                    memchr(b'\n', &slice[range.end..]).map_or(slice.len(), |i| range.end + i + 1);
+                break;
+            }
+        }
+
+        sink.finish(bytes_read as u64)
+    }
+
+    fn search_multi_line(
+        &self,
+        matcher: &impl Matcher,
+        slice: &[u8],
+        sink: &mut impl Sink,
+    ) -> io::Result<()> {
+        let text = String::from_utf8_lossy(slice);
+        let mut number = 1;
+        let mut counted = 0;
+
+        for block in multiline::blocks(matcher, &text) {
+            number += memchr_iter(b'\n', &text.as_bytes()[counted..block.lines.start]).count();
+            counted = block.lines.start;
+
+            let start = block.lines.start;
+            let found = LineMatch {
+                line_number: number,
+                byte_offset: start as u64,
+                line: text[block.lines.clone()].to_string(),
+                // A match can take the last line's `\n` along.
+                matches: block
+                    .matches
+                    .iter()
+                    .map(|m| m.start - start..m.end.min(block.lines.end) - start)
+                    .collect(),
+                distance: None,
+            };
+            if !sink.matched(&found)? {
+                break;
+            }
+        }
+
+        // All of it was read to begin with.
+        sink.finish(slice.len() as u64)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+    use crate::matcher::{case_insensitive, Literal};
+
+    const POEM: &str = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+    // Renders results the way grep does, and stops after `limit` matches.
+    struct Render {
+        lines: Vec<String>,
+        limit: usize,
+        bytes_read: u64,
+    }
+
+    impl Sink for Render {
+        fn matched(&mut self, found: &LineMatch) -> io::Result<bool> {
+            self.lines
+                .push(format!("{}:{}", found.line_number, found.line));
+            self.limit -= 1;
+            Ok(self.limit > 0)
+        }
+
+        fn context(&mut self, line: &LineMatch) -> io::Result<bool> {
+            self.lines
+                .push(format!("{}-{}", line.line_number, line.line));
+            Ok(true)
+        }
+
+        fn context_break(&mut self) -> io::Result<bool> {
+            self.lines.push("--".to_string());
+            Ok(true)
+        }
+
+        fn finish(&mut self, bytes_read: u64) -> io::Result<()> {
+            self.bytes_read = bytes_read;
+            Ok(())
+        }
+    }
+
+    fn render(searcher: Searcher, matcher: impl Matcher, limit: usize) -> Render {
+        let mut sink = Render {
+            lines: Vec::new(),
+            limit,
+            bytes_read: 0,
+        };
+        searcher
+            .search_slice(matcher, POEM.as_bytes(), &mut sink)
+            .unwrap();
+        sink
+    }
+
+    #[test]
+    fn reports_matches_and_context() {
+        let searcher = Searcher::new().after_context(1);
+        let sink = render(searcher, case_insensitive("RUST"), usize::MAX);
+
+        assert_eq!(vec!["1:Rust:", "2-safe, fast, productive."], sink.lines);
+        assert_eq!(POEM.len() as u64, sink.bytes_read);
+    }
+
+    #[test]
+    fn sinks_can_stop_early() {
+        let searcher = Searcher::new().invert_match(true);
+        let sink = render(searcher, Literal::new("Duct"), 1);
+
+        assert_eq!(vec!["1:Rust:"], sink.lines);
+        assert!(sink.bytes_read < POEM.len() as u64);
+    }
+
+    #[test]
+    fn collects_into_a_vec() {
+        let mut found: Vec<LineMatch> = Vec::new();
+        Searcher::new()
+            .search_slice(Literal::new("ct"), POEM.as_bytes(), &mut found)
+            .unwrap();
+
+        let lines: Vec<usize> = found.iter().map(|m| m.line_number).collect();
+        assert_eq!(vec![2, 4], lines);
+    }
+
+    #[test]
+    fn literal_fast_path_agrees_with_streaming() {
+        let input = b"duct tape\r\nRust\n\nduct, duct\nno\xff duct";
+
+        for needle in ["duct", "t", "Rust", "\u{FFFD}"] {
+            let (mut fast, mut slow) = (Vec::new(), Vec::new());
+            let searcher = Searcher::new();
+            searcher
+                .search_slice(Literal::new(needle), input, &mut fast)
+                .unwrap();
+            searcher
+                .search_reader(Literal::new(needle), &input[..], &mut slow)
+                .unwrap();
+            assert_eq!(slow, fast, "searching for {:?}", needle);
+        }
+
+        // Stopping early reads up to the end of the last line either way.
+        let fast = render(Searcher::new(), Literal::new("t"), 2);
+        let mut slow = Render {
+            lines: Vec::new(),
+            limit: 2,
+            bytes_read: 0,
+        };
+        Searcher::new()
+            .search_reader(Literal::new("t"), POEM.as_bytes(), &mut slow)
+            .unwrap();
+        assert_eq!(slow.bytes_read, fast.bytes_read);
+        assert_eq!(slow.lines, fast.lines);
+    }
+
+    #[test]
+    fn multi_line_blocks() {
+        let mut found: Vec<LineMatch> = Vec::new();
+        Searcher::new()
+            .multi_line(true)
+            .search_slice(Literal::new("three.\nDuct"), POEM.as_bytes(), &mut found)
+            .unwrap();
+
+        assert_eq!(1, found.len());
+        assert_eq!(3, found[0].line_number);
+        assert_eq!("Pick three.\nDuct tape.", found[0].line);
+        assert_eq!(vec![5..16], found[0].matches);
+    }
+}
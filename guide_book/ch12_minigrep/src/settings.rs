@@ -0,0 +1,298 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The project-local settings file, looked for in the current directory
+/// and each of its parents.
+pub const PROJECT_FILE: &str = ".minigreprc";
+
+/// Every variable named this plus a flag's long name, upper-cased and
+/// with `_` for `-`, sets that flag: `MINIGREP_LINE_NUMBER=1`.
+const ENV_PREFIX: &str = "MINIGREP_";
+
+/// A default for one flag, named by its long name without the dashes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Setting {
+    pub name: String,
+    pub value: Value,
+    pub source: Source,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// For flags without a value: whether it's on.
+    Switch(bool),
+    Text(String),
+}
+
+/// Where a setting came from; later sources take precedence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// `config.toml` in the user's config directory.
+    UserFile(PathBuf),
+    /// The nearest [`PROJECT_FILE`].
+    ProjectFile(PathBuf),
+    /// An environment variable, by name.
+    Env(String),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::UserFile(path) | Source::ProjectFile(path) => write!(f, "{}", path.display()),
+            Source::Env(name) => write!(f, "${}", name),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Switch(on) => write!(f, "{}", on),
+            Value::Text(text) => write!(f, "{:?}", text),
+        }
+    }
+}
+
+/// The settings files that apply here, whether or not they exist.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Files {
+    pub user: Option<PathBuf>,
+    pub project: Option<PathBuf>,
+}
+
+impl Files {
+    /// The user file lives in `$XDG_CONFIG_HOME`, or `~/.config` without
+    /// it; the project file is the nearest one above the current directory.
+    pub fn locate() -> Files {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        let cwd = env::current_dir().ok();
+
+        Files {
+            user: config_home.map(|dir| dir.join("minigrep").join("config.toml")),
+            project: cwd.and_then(|cwd| {
+                cwd.ancestors()
+                    .map(|dir| dir.join(PROJECT_FILE))
+                    .find(|path| path.is_file())
+            }),
+        }
+    }
+
+    /// The settings of both files, the user's first. Missing files are
+    /// simply skipped.
+    pub fn read(&self) -> Result<Vec<Setting>, FileError> {
+        let mut settings = Vec::new();
+        if let Some(path) = &self.user {
+            settings.extend(read_file(path, Source::UserFile(path.clone()))?);
+        }
+        if let Some(path) = &self.project {
+            settings.extend(read_file(path, Source::ProjectFile(path.clone()))?);
+        }
+        Ok(settings)
+    }
+}
+
+/// A settings file that exists but can't be used.
+#[derive(Debug)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+// Both files are TOML, one `name = value` per flag:
+//
+//     line-number = true
+//     context = 2
+//     color = "never"
+fn read_file(path: &Path, source: Source) -> Result<Vec<Setting>, FileError> {
+    let error = |message: String| FileError {
+        path: path.to_path_buf(),
+        message,
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(error(err.to_string())),
+    };
+    let table: toml::Table = text
+        .parse()
+        .map_err(|err: toml::de::Error| error(err.message().to_string()))?;
+
+    table
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                toml::Value::Boolean(on) => Value::Switch(on),
+                toml::Value::Integer(n) => Value::Text(n.to_string()),
+                toml::Value::String(text) => Value::Text(text),
+                other => {
+                    let message = format!("'{}' must be a boolean, number or string", name);
+                    return Err(error(format!("{}, not {}", message, other.type_str())));
+                }
+            };
+            Ok(Setting {
+                name,
+                value,
+                source: source.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The `MINIGREP_*` variables among `vars`, plus `IGNORE_CASE`, which
+/// turns on `ignore-case` just by being set. `is_switch` tells, for a
+/// flag's long name, whether it takes no value, so that `1`, `true`,
+/// `0`, `false` and the like can be read as on or off; it returns `None`
+/// for names that aren't flags. Those variables are skipped, since the
+/// environment may hold anything.
+pub fn from_env(
+    vars: impl Iterator<Item = (String, String)>,
+    is_switch: impl Fn(&str) -> Option<bool>,
+) -> Vec<Setting> {
+    let mut legacy = Vec::new();
+    let mut settings = Vec::new();
+
+    for (var, text) in vars {
+        if var == "IGNORE_CASE" {
+            legacy.push(Setting {
+                name: "ignore-case".to_string(),
+                value: Value::Switch(true),
+                source: Source::Env(var),
+            });
+            continue;
+        }
+        let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let name = name.to_lowercase().replace('_', "-");
+        let Some(switch) = is_switch(&name) else {
+            continue;
+        };
+        let value = if switch {
+            match text.to_lowercase().as_str() {
+                "" | "0" | "false" | "no" | "off" => Value::Switch(false),
+                _ => Value::Switch(true),
+            }
+        } else {
+            Value::Text(text)
+        };
+        settings.push(Setting {
+            name,
+            value,
+            source: Source::Env(var),
+        });
+    }
+
+    // The order of the environment means nothing, so go by name.
+    settings.sort_by(|a, b| a.name.cmp(&b.name));
+    legacy.append(&mut settings);
+    legacy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn reads_toml_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("config.toml");
+        let project = dir.path().join(PROJECT_FILE);
+        fs::write(&user, "line-number = true\ncolor = \"never\"\n").unwrap();
+        fs::write(&project, "context = 2\nline-number = false\n").unwrap();
+
+        let files = Files {
+            user: Some(user.clone()),
+            project: Some(project.clone()),
+        };
+        let settings = files.read().unwrap();
+
+        let found: Vec<(&str, Value)> = settings
+            .iter()
+            .map(|s| (s.name.as_str(), s.value.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("color", Value::Text("never".to_string())),
+                ("line-number", Value::Switch(true)),
+                ("context", Value::Text("2".to_string())),
+                ("line-number", Value::Switch(false)),
+            ],
+            found
+        );
+        assert_eq!(Source::UserFile(user), settings[0].source);
+        assert_eq!(Source::ProjectFile(project), settings[3].source);
+    }
+
+    #[test]
+    fn missing_and_broken_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = Files {
+            user: Some(dir.path().join("missing.toml")),
+            project: None,
+        };
+        assert!(files.read().unwrap().is_empty());
+
+        let broken = dir.path().join("broken.toml");
+        fs::write(&broken, "context = [1, 2]\n").unwrap();
+        let files = Files {
+            user: None,
+            project: Some(broken.clone()),
+        };
+        let err = files.read().unwrap_err();
+        assert_eq!(broken, err.path);
+        assert!(err.message.contains("not array"), "{}", err.message);
+    }
+
+    #[test]
+    fn reads_env_vars() {
+        let is_switch = |name: &str| match name {
+            "line-number" | "ignore-case" => Some(true),
+            "context" => Some(false),
+            _ => None,
+        };
+        let settings = from_env(
+            vars(&[
+                ("MINIGREP_LINE_NUMBER", "0"),
+                ("PATH", "/bin"),
+                ("MINIGREP_CONTEXT", "3"),
+                ("MINIGREP_FOO", "1"),
+                ("IGNORE_CASE", ""),
+            ]),
+            is_switch,
+        );
+
+        let found: Vec<(&str, Value)> = settings
+            .iter()
+            .map(|s| (s.name.as_str(), s.value.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("ignore-case", Value::Switch(true)),
+                ("context", Value::Text("3".to_string())),
+                ("line-number", Value::Switch(false)),
+            ],
+            found
+        );
+        assert_eq!(
+            Source::Env("MINIGREP_CONTEXT".to_string()),
+            settings[1].source
+        );
+    }
+}
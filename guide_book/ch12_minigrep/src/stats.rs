@@ -0,0 +1,120 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What searching one input turned up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStats {
+    pub bytes_searched: u64,
+    pub matched_lines: u64,
+    /// Individual occurrences; a line can hold several.
+    pub matches: u64,
+}
+
+impl FileStats {
+    pub(crate) fn record(&mut self, found: &crate::LineMatch) {
+        self.matched_lines += 1;
+        self.matches += found.matches.len() as u64;
+    }
+}
+
+/// Totals over every input of a run, as returned by [`run`](crate::run).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub files_searched: u64,
+    pub files_with_match: u64,
+    pub bytes_searched: u64,
+    pub matched_lines: u64,
+    pub matches: u64,
+    /// The inputs that had a match, in the order they were printed.
+    pub per_file: Vec<(PathBuf, FileStats)>,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    pub(crate) fn add(&mut self, path: &Path, stats: FileStats) {
+        self.files_searched += 1;
+        if stats.matched_lines > 0 {
+            self.files_with_match += 1;
+            self.per_file.push((path.to_path_buf(), stats));
+        }
+        self.bytes_searched += stats.bytes_searched;
+        self.matched_lines += stats.matched_lines;
+        self.matches += stats.matches;
+    }
+}
+
+/// The `--stats` report.
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} matches", self.matches)?;
+        writeln!(f, "{} matched lines", self.matched_lines)?;
+        writeln!(f, "{} files contained matches", self.files_with_match)?;
+        writeln!(f, "{} files searched", self.files_searched)?;
+        writeln!(f, "{} bytes searched", self.bytes_searched)?;
+        writeln!(f, "{:.6} seconds", self.elapsed.as_secs_f64())?;
+
+        if !self.per_file.is_empty() {
+            writeln!(f, "matches per file:")?;
+        }
+        for (path, stats) in &self.per_file {
+            writeln!(
+                f,
+                "  {}: {} matches in {} lines",
+                path.display(),
+                stats.matches,
+                stats.matched_lines
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_up_files() {
+        let mut stats = Stats::default();
+        let a = FileStats {
+            bytes_searched: 100,
+            matched_lines: 2,
+            matches: 3,
+        };
+        stats.add(Path::new("a.txt"), a);
+        stats.add(
+            Path::new("b.txt"),
+            FileStats {
+                bytes_searched: 50,
+                ..FileStats::default()
+            },
+        );
+
+        assert_eq!((2, 1), (stats.files_searched, stats.files_with_match));
+        assert_eq!(
+            (150, 2, 3),
+            (stats.bytes_searched, stats.matched_lines, stats.matches)
+        );
+        assert_eq!(vec![(PathBuf::from("a.txt"), a)], stats.per_file);
+    }
+
+    #[test]
+    fn report() {
+        let mut stats = Stats::default();
+        stats.add(
+            Path::new("a.txt"),
+            FileStats {
+                bytes_searched: 100,
+                matched_lines: 2,
+                matches: 3,
+            },
+        );
+        stats.elapsed = Duration::from_millis(1);
+
+        let report = stats.to_string();
+        assert!(report.starts_with("3 matches\n2 matched lines\n1 files contained matches\n"));
+        assert!(report.contains("0.001000 seconds\n"));
+        assert!(report.ends_with("matches per file:\n  a.txt: 3 matches in 2 lines\n"));
+    }
+}
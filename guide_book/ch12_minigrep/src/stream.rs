@@ -0,0 +1,360 @@
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::context::ContextStream;
+use crate::fold::{FoldOptions, FoldedQuery};
+use crate::fuzzy::FuzzyQuery;
+use crate::matcher::Matcher;
+use crate::query::Query;
+
+/// The test a single line has to pass to be reported.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Literal(String),
+    /// Compared after Unicode case folding and/or normalization.
+    Folded(FoldedQuery),
+    Regex(Regex),
+    /// Several terms at once, combined by a boolean expression.
+    Query(Query),
+    /// Within a Levenshtein distance of the query.
+    Fuzzy(FuzzyQuery),
+}
+
+impl Pattern {
+    pub fn literal(query: &str) -> Pattern {
+        Pattern::Literal(query.to_string())
+    }
+
+    pub fn ignore_case(query: &str) -> Pattern {
+        let options = FoldOptions {
+            case_fold: true,
+            ..FoldOptions::default()
+        };
+        Pattern::Folded(FoldedQuery::new(query, options))
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            Pattern::Literal(query) => line.contains(query.as_str()),
+            Pattern::Folded(query) => query.is_match(line),
+            Pattern::Regex(regex) => regex.is_match(line),
+            Pattern::Query(query) => query.is_match(line),
+            Pattern::Fuzzy(query) => query.is_match(line),
+        }
+    }
+
+    /// Byte ranges of every non-overlapping match within `line`.
+    pub fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        match self {
+            Pattern::Literal(query) => line
+                .match_indices(query.as_str())
+                .map(|(start, found)| start..start + found.len())
+                .collect(),
+            Pattern::Folded(query) => query.find_iter(line),
+            Pattern::Regex(regex) => regex.find_iter(line).map(|m| m.range()).collect(),
+            Pattern::Query(query) => query.find_iter(line),
+            Pattern::Fuzzy(query) => query.find_iter(line).into_iter().map(|m| m.range).collect(),
+        }
+    }
+
+    /// Substitutes `template` for every match in `line`, returning the
+    /// new line along with where the replacements ended up in it.
+    ///
+    /// For regexes, `$1`, `${name}` and so on expand to capture groups
+    /// (`$$` is a literal `$`); any other pattern inserts `template` as is.
+    pub fn replace(&self, line: &str, template: &str) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut ranges = Vec::new();
+        let mut copied = 0;
+
+        let mut push = |range: Range<usize>, expand: &dyn Fn(&mut String)| {
+            replaced.push_str(&line[copied..range.start]);
+            let start = replaced.len();
+            expand(&mut replaced);
+            ranges.push(start..replaced.len());
+            copied = range.end;
+        };
+
+        match self {
+            Pattern::Regex(regex) => {
+                for caps in regex.captures_iter(line) {
+                    let range = caps.get(0).map_or(0..0, |m| m.range());
+                    push(range, &|dst| caps.expand(template, dst));
+                }
+            }
+            _ => {
+                for range in self.find_iter(line) {
+                    push(range, &|dst| dst.push_str(template));
+                }
+            }
+        }
+
+        replaced.push_str(&line[copied..]);
+        (replaced, ranges)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineMatch {
+    /// 1-based, counted over every line read, not just the matching ones.
+    pub line_number: usize,
+    /// Where the line starts, in bytes from the beginning of the input.
+    pub byte_offset: u64,
+    pub line: String,
+    /// Byte ranges within `line` that matched; empty for context lines
+    /// and for lines selected by an inverted search.
+    pub matches: Vec<Range<usize>>,
+    /// For fuzzy searches, the edit distance of the closest match.
+    pub distance: Option<usize>,
+}
+
+impl LineMatch {
+    pub(crate) fn new(line: &Line, matcher: &impl Matcher, is_match: bool) -> LineMatch {
+        let (matches, distance) = if is_match {
+            (matcher.find_iter(line.text), matcher.distance(line.text))
+        } else {
+            (Vec::new(), None)
+        };
+
+        LineMatch {
+            line_number: line.number,
+            byte_offset: line.offset,
+            line: line.text.to_string(),
+            matches,
+            distance,
+        }
+    }
+}
+
+pub(crate) struct Line<'a> {
+    /// 1-based.
+    pub(crate) number: usize,
+    /// Byte offset of the start of the line.
+    pub(crate) offset: u64,
+    pub(crate) text: &'a str,
+}
+
+/// Reads `\n`-terminated lines into a reused buffer, counting them as it goes.
+///
+/// Invalid UTF-8 is replaced with U+FFFD rather than reported as an error,
+/// so one stray byte doesn't end the search of an otherwise fine file.
+pub(crate) struct LineReader<R> {
+    reader: R,
+    line_number: usize,
+    line_offset: u64,
+    bytes_read: u64,
+    buf: Vec<u8>,
+    // Holds the repaired copy of a line that wasn't valid UTF-8.
+    lossy: String,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub(crate) fn new(reader: R) -> LineReader<R> {
+        LineReader {
+            reader,
+            line_number: 0,
+            line_offset: 0,
+            bytes_read: 0,
+            buf: Vec::new(),
+            lossy: String::new(),
+        }
+    }
+
+    /// Returns the next line, or `None` at EOF.
+    pub(crate) fn next_line(&mut self) -> Option<io::Result<Line<'_>>> {
+        self.buf.clear();
+        let read = match self.reader.read_until(b'\n', &mut self.buf) {
+            Ok(0) => return None,
+            Ok(read) => read,
+            Err(err) => return Some(Err(err)),
+        };
+        self.line_number += 1;
+        self.line_offset = self.bytes_read;
+        self.bytes_read += read as u64;
+
+        // Strip the terminator the same way `str::lines` does.
+        if self.buf.ends_with(b"\n") {
+            self.buf.pop();
+            if self.buf.ends_with(b"\r") {
+                self.buf.pop();
+            }
+        }
+
+        let text = match std::str::from_utf8(&self.buf) {
+            Ok(text) => text,
+            Err(_) => {
+                self.lossy = String::from_utf8_lossy(&self.buf).into_owned();
+                &self.lossy
+            }
+        };
+
+        Some(Ok(Line {
+            number: self.line_number,
+            offset: self.line_offset,
+            text,
+        }))
+    }
+
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+/// Lazily searches `reader` one line at a time, so memory use stays
+/// bounded by the longest line rather than the size of the input.
+pub struct SearchStream<R, M = Pattern> {
+    lines: LineReader<R>,
+    matcher: M,
+    invert_match: bool,
+}
+
+impl<R: BufRead, M: Matcher> SearchStream<R, M> {
+    pub fn new(reader: R, matcher: M) -> SearchStream<R, M> {
+        SearchStream {
+            lines: LineReader::new(reader),
+            matcher,
+            invert_match: false,
+        }
+    }
+
+    /// Yields the lines that do *not* match instead.
+    pub fn invert_match(mut self, yes: bool) -> SearchStream<R, M> {
+        self.invert_match = yes;
+        self
+    }
+
+    /// How much of the input has been consumed so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.lines.bytes_read()
+    }
+
+    /// Also reports up to `before` lines preceding and `after` lines
+    /// following each match; see [`ContextStream`].
+    pub fn with_context(self, before: usize, after: usize) -> ContextStream<R, M> {
+        ContextStream::new(self.lines, self.matcher, self.invert_match, before, after)
+    }
+}
+
+impl<R: BufRead, M: Matcher> Iterator for SearchStream<R, M> {
+    type Item = io::Result<LineMatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next_line()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+
+            let is_match = self.matcher.is_match(line.text);
+            if is_match != self.invert_match {
+                return Some(Ok(LineMatch::new(&line, &self.matcher, is_match)));
+            }
+        }
+    }
+}
+
+pub fn search_reader<R: BufRead, M: Matcher>(matcher: M, reader: R) -> SearchStream<R, M> {
+    SearchStream::new(reader, matcher)
+}
+
+#[cfg(test)]
+// `vec![0..5]` is a list holding one range here, not a mistake.
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn yields_numbered_matches() {
+        let input = Cursor::new("Rust:\r\nsafe, fast, productive.\nPick three.\nTrust me.");
+        let matches: Vec<LineMatch> = search_reader(Pattern::ignore_case("RUST"), input)
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(
+            vec![
+                LineMatch {
+                    line_number: 1,
+                    byte_offset: 0,
+                    line: "Rust:".to_string(),
+                    matches: vec![0..4],
+                    distance: None,
+                },
+                LineMatch {
+                    line_number: 4,
+                    byte_offset: 43,
+                    line: "Trust me.".to_string(),
+                    matches: vec![1..5],
+                    distance: None,
+                },
+            ],
+            matches
+        );
+    }
+
+    #[test]
+    fn inverted() {
+        let input = Cursor::new("a\nb\na\n");
+        let lines: Vec<usize> = search_reader(Pattern::literal("a"), input)
+            .invert_match(true)
+            .map(|m| m.unwrap().line_number)
+            .collect();
+
+        assert_eq!(vec![2], lines);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let input = Cursor::new(&b"ok\n\xff caf\xe9 ok\nok\n"[..]);
+        let lines: Vec<(usize, String)> = search_reader(Pattern::literal("ok"), input)
+            .map(|m| m.map(|m| (m.line_number, m.line)).unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![
+                (1, "ok".to_string()),
+                (2, "\u{fffd} caf\u{fffd} ok".to_string()),
+                (3, "ok".to_string()),
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn match_ranges() {
+        let pattern = Pattern::Regex(Regex::new(r"\d{3}").unwrap());
+        assert_eq!(vec![6..9, 10..13], pattern.find_iter("ERROR 503 504"));
+
+        assert_eq!(vec![2..4], Pattern::literal("ab").find_iter("xxab"));
+    }
+
+    #[test]
+    fn replace_with_capture_groups() {
+        let pattern = Pattern::Regex(Regex::new(r"(?<code>\d{3}) (\w+)").unwrap());
+        assert_eq!(
+            ("ERROR [503] upstream=$".to_string(), vec![6..22]),
+            pattern.replace("ERROR 503 upstream", "[$code] ${2}=$$")
+        );
+
+        let (line, ranges) = Pattern::literal("a").replace("banana", "o");
+        assert_eq!(
+            ("bonono".to_string(), vec![1..2, 3..4, 5..6]),
+            (line, ranges)
+        );
+
+        let (line, _) = Pattern::ignore_case("RUST").replace("Rust, rust", "$1");
+        assert_eq!("$1, $1", line);
+    }
+
+    #[test]
+    fn case_insensitive_ranges_point_into_the_original() {
+        // 'İ' is two bytes but folds to three.
+        let pattern = Pattern::ignore_case("stra");
+        assert_eq!(vec![4..8], pattern.find_iter("İ, STRAẞE"));
+
+        let pattern = Pattern::ignore_case("i");
+        assert_eq!(vec![0..2, 4..5], pattern.find_iter("İ, i"));
+    }
+}
@@ -0,0 +1,361 @@
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use csv::ByteRecord;
+use serde_json::Value;
+
+use crate::stream::LineReader;
+use crate::{LineMatch, Pattern};
+
+/// The one field of each record that the pattern is matched against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// The CSV column with this name in the header row.
+    CsvColumn(String),
+    /// A path into each JSON Lines object, one key (or array index) per
+    /// segment; empty for the whole value.
+    JsonPath(Vec<String>),
+}
+
+impl Field {
+    /// Parses a path like `.a.b` or `.items.0.name`.
+    pub fn json_path(path: &str) -> Option<Field> {
+        let segments = path.strip_prefix('.')?;
+        if segments.is_empty() {
+            return Some(Field::JsonPath(Vec::new()));
+        }
+
+        let segments: Vec<String> = segments.split('.').map(String::from).collect();
+        if segments.iter().any(String::is_empty) {
+            return None;
+        }
+        Some(Field::JsonPath(segments))
+    }
+}
+
+/// Like [`SearchStream`](crate::SearchStream), but yields whole records
+/// whose `field` matches rather than lines: a CSV record (quoted fields
+/// can span lines) or a JSON Lines object.
+pub struct RecordStream<R> {
+    records: Records<R>,
+    pattern: Pattern,
+    invert_match: bool,
+}
+
+enum Records<R> {
+    Csv {
+        reader: csv::Reader<R>,
+        column: usize,
+        record: ByteRecord,
+    },
+    Json {
+        lines: LineReader<R>,
+        path: Vec<String>,
+    },
+}
+
+impl<R: BufRead> RecordStream<R> {
+    /// Fails if `field` names a CSV column that isn't in the header row.
+    pub fn new(field: &Field, pattern: Pattern, reader: R) -> io::Result<RecordStream<R>> {
+        let records = match field {
+            Field::CsvColumn(name) => {
+                let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+                let headers = reader.byte_headers().map_err(io_error)?;
+                let column = headers
+                    .iter()
+                    .position(|header| header == name.as_bytes())
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("no column named '{}'", name),
+                        )
+                    })?;
+                Records::Csv {
+                    reader,
+                    column,
+                    record: ByteRecord::new(),
+                }
+            }
+            Field::JsonPath(path) => Records::Json {
+                lines: LineReader::new(reader),
+                path: path.clone(),
+            },
+        };
+
+        Ok(RecordStream {
+            records,
+            pattern,
+            invert_match: false,
+        })
+    }
+
+    /// Yields the records that do *not* match instead.
+    pub fn invert_match(mut self, yes: bool) -> RecordStream<R> {
+        self.invert_match = yes;
+        self
+    }
+
+    /// How much of the input has been consumed so far.
+    pub fn bytes_read(&self) -> u64 {
+        match &self.records {
+            Records::Csv { reader, .. } => reader.position().byte(),
+            Records::Json { lines, .. } => lines.bytes_read(),
+        }
+    }
+
+    fn next_csv(&mut self) -> Option<io::Result<LineMatch>> {
+        let Records::Csv {
+            reader,
+            column,
+            record,
+        } = &mut self.records
+        else {
+            unreachable!();
+        };
+
+        loop {
+            match reader.read_byte_record(record) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => return Some(Err(io_error(err))),
+            }
+
+            let fields: Vec<String> = record
+                .iter()
+                .map(|field| String::from_utf8_lossy(field).into_owned())
+                .collect();
+            // Short rows simply don't have the column, so can't match.
+            let is_match = fields
+                .get(*column)
+                .is_some_and(|field| self.pattern.is_match(field));
+            if is_match == self.invert_match {
+                continue;
+            }
+
+            let mut line = String::new();
+            let mut matches = Vec::new();
+            for (i, field) in fields.iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                let start = line.len();
+                let quoted = quote(field, &mut line);
+                // Doubled quotes would shift the ranges, so those fields
+                // just go without highlighting.
+                if i == *column && is_match && !field.contains('"') {
+                    let shift = start + usize::from(quoted);
+                    matches = shifted(self.pattern.find_iter(field), shift);
+                }
+            }
+
+            // Records read by the csv reader always know their position.
+            let position = record.position().expect("record without a position");
+            return Some(Ok(LineMatch {
+                line_number: position.line() as usize,
+                byte_offset: position.byte(),
+                line,
+                matches,
+                distance: None,
+            }));
+        }
+    }
+
+    fn next_json(&mut self) -> Option<io::Result<LineMatch>> {
+        let Records::Json { lines, path } = &mut self.records else {
+            unreachable!();
+        };
+
+        loop {
+            let line = match lines.next_line()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+
+            // Lines that aren't JSON, or lack the field, don't match.
+            let value = serde_json::from_str::<Value>(line.text).ok();
+            let field = value.as_ref().and_then(|value| lookup(value, path));
+            let text = field.map(|field| match field {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            });
+            let is_match = text
+                .as_ref()
+                .is_some_and(|text| self.pattern.is_match(text));
+            if is_match == self.invert_match {
+                continue;
+            }
+
+            let mut matches = Vec::new();
+            if let (true, Some(field), Some(text)) = (is_match, field, &text) {
+                // Highlight the field where it appears verbatim in the line,
+                // which it does unless its string needed escaping. If it
+                // appears more than once, say as a key too, there's no
+                // telling which is the field, so nothing is highlighted.
+                let raw = field.to_string();
+                let quoted = field.is_string();
+                let verbatim = !quoted || raw.len() == text.len() + 2;
+                let mut found = line.text.match_indices(&raw).map(|(start, _)| start);
+                if let (true, Some(start), None) = (verbatim, found.next(), found.next()) {
+                    let shift = start + usize::from(quoted);
+                    matches = shifted(self.pattern.find_iter(text), shift);
+                }
+            }
+
+            return Some(Ok(LineMatch {
+                line_number: line.number,
+                byte_offset: line.offset,
+                line: line.text.to_string(),
+                matches,
+                distance: None,
+            }));
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for RecordStream<R> {
+    type Item = io::Result<LineMatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.records {
+            Records::Csv { .. } => self.next_csv(),
+            Records::Json { .. } => self.next_json(),
+        }
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+// Appends `field` to `line`, quoted only if it has to be, and says
+// whether it was.
+fn quote(field: &str, line: &mut String) -> bool {
+    let needs_quotes = field.contains([',', '"', '\n', '\r']);
+    if needs_quotes {
+        line.push('"');
+        line.push_str(&field.replace('"', "\"\""));
+        line.push('"');
+    } else {
+        line.push_str(field);
+    }
+    needs_quotes
+}
+
+fn shifted(ranges: Vec<Range<usize>>, shift: usize) -> Vec<Range<usize>> {
+    ranges
+        .into_iter()
+        .map(|range| range.start + shift..range.end + shift)
+        .collect()
+}
+
+fn io_error(err: csv::Error) -> io::Error {
+    if !err.is_io_error() {
+        return io::Error::new(io::ErrorKind::InvalidData, err);
+    }
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => err,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn search(field: &Field, query: &str, input: &str) -> Vec<LineMatch> {
+        RecordStream::new(field, Pattern::literal(query), input.as_bytes())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn csv_column_only() {
+        let input = "\
+name,city,note
+Ana,Lisbon,moved to Porto
+Bruno,Porto,\"born in
+Lisbon, grew up in Porto\"
+";
+        let column = Field::CsvColumn("city".to_string());
+
+        let found = search(&column, "Porto", input);
+        assert_eq!(1, found.len());
+        assert_eq!(3, found[0].line_number);
+        assert_eq!(
+            "Bruno,Porto,\"born in\nLisbon, grew up in Porto\"",
+            found[0].line
+        );
+        assert_eq!(vec![6..11], found[0].matches);
+
+        let column = Field::CsvColumn("note".to_string());
+        let found = search(&column, "Lisbon", input);
+        assert_eq!(
+            vec![3],
+            found.iter().map(|m| m.line_number).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![21..27], found[0].matches);
+    }
+
+    #[test]
+    fn unknown_csv_column() {
+        let column = Field::CsvColumn("zip".to_string());
+        let err = RecordStream::new(&column, Pattern::literal("x"), &b"a,b\n1,2\n"[..])
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn json_path() {
+        let input = r#"{"level":"error","ctx":{"user":"ana"},"msg":"ana logged in"}
+{"level":"info","ctx":{"user":"bruno"},"msg":"ana? no"}
+not json at all, ana
+{"level":"warn","ctx":{"user":["x"]},"msg":"ana"}"#;
+        let path = Field::json_path(".ctx.user").unwrap();
+
+        let found = search(&path, "ana", input);
+        assert_eq!(
+            vec![1],
+            found.iter().map(|m| m.line_number).collect::<Vec<_>>()
+        );
+        assert_eq!(input.lines().next().unwrap(), found[0].line);
+        assert_eq!(vec![32..35], found[0].matches);
+
+        let path = Field::json_path(".ctx.user.0").unwrap();
+        assert_eq!(4, search(&path, "x", input)[0].line_number);
+
+        // The value also appears elsewhere, so it can't be pinned down.
+        let found = search(
+            &Field::json_path(".ctx.user").unwrap(),
+            "ana",
+            r#"{"name":"ana","ctx":{"user":"ana"}}"#,
+        );
+        assert_eq!(1, found.len());
+        assert!(found[0].matches.is_empty());
+    }
+
+    #[test]
+    fn non_string_json_values() {
+        let path = Field::json_path(".status").unwrap();
+        let found = search(&path, "503", "{\"status\":503}\n{\"status\":200}\n");
+        assert_eq!(1, found.len());
+        assert_eq!(vec![10..13], found[0].matches);
+    }
+
+    #[test]
+    fn parses_json_paths() {
+        assert_eq!(
+            Some(Field::JsonPath(vec!["a".to_string(), "b".to_string()])),
+            Field::json_path(".a.b")
+        );
+        assert_eq!(Some(Field::JsonPath(Vec::new())), Field::json_path("."));
+        assert_eq!(None, Field::json_path("a.b"));
+        assert_eq!(None, Field::json_path(".a..b"));
+    }
+}
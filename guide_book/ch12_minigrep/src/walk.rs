@@ -0,0 +1,112 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+use crate::index::INDEX_FILE;
+
+// Only the first few KiB are inspected, the same heuristic grep uses.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Recursively collects every regular file under `root`, hidden ones
+/// included, honoring `.gitignore`, `.ignore` and global git excludes
+/// along the way. The only file left out regardless is minigrep's own
+/// [`INDEX_FILE`].
+/// Directory entries are visited in file name order, so the output
+/// doesn't depend on the order the file system happens to list them.
+///
+/// Entries that can't be read (permission denied, broken symlinks, ...)
+/// are yielded as errors so the caller can report them and carry on.
+pub fn files(root: &Path) -> impl Iterator<Item = Result<PathBuf, ignore::Error>> {
+    WalkBuilder::new(root)
+        // Respect ignore files even when `root` isn't inside a git repo.
+        .require_git(false)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != INDEX_FILE)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => {
+                let is_file = entry.file_type().is_some_and(|ft| ft.is_file());
+                is_file.then(|| Ok(entry.into_path()))
+            }
+            Err(err) => Some(Err(err)),
+        })
+}
+
+/// Splits an error yielded by [`files`] into the path it's about, when it
+/// names one, and the underlying I/O error.
+pub fn split_error(err: ignore::Error) -> (Option<PathBuf>, io::Error) {
+    match err {
+        ignore::Error::WithPath { path, err } => (Some(path), split_error(*err).1),
+        ignore::Error::WithDepth { err, .. } => split_error(*err),
+        ignore::Error::Io(err) => (None, err),
+        ignore::Error::Loop { ref child, .. } => (Some(child.clone()), io::Error::other(err)),
+        err => (None, io::Error::other(err)),
+    }
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let len = bytes.len().min(BINARY_SNIFF_LEN);
+    bytes[..len].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn walks_recursively_and_honors_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        fs::write(root.join("src/nested/mod.rs"), "").unwrap();
+        fs::write(root.join("src/debug.log"), "").unwrap();
+        fs::write(root.join("target/out.rs"), "").unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(root.join(".env"), "").unwrap();
+        fs::write(root.join(".hidden/c.txt"), "").unwrap();
+        fs::write(root.join(INDEX_FILE), "").unwrap();
+
+        let mut found: Vec<PathBuf> = files(root)
+            .map(|path| path.unwrap().strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        found.sort();
+
+        assert_eq!(
+            vec![
+                PathBuf::from(".env"),
+                PathBuf::from(".gitignore"),
+                PathBuf::from(".hidden/c.txt"),
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/nested/mod.rs")
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn splits_errors() {
+        let err = ignore::Error::WithDepth {
+            depth: 1,
+            err: Box::new(ignore::Error::WithPath {
+                path: PathBuf::from("dir/secret"),
+                err: Box::new(ignore::Error::Io(io::ErrorKind::PermissionDenied.into())),
+            }),
+        };
+        let (path, err) = split_error(err);
+        assert_eq!(Some(PathBuf::from("dir/secret")), path);
+        assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+    }
+
+    #[test]
+    fn nul_bytes_mean_binary() {
+        assert!(!is_binary(b"plain text\nwith lines\n"));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00\x00"));
+    }
+}
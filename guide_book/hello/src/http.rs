@@ -0,0 +1,469 @@
+use std::{
+    fmt,
+    io::{self, prelude::*},
+};
+
+/// The longest request line or header line accepted, terminator included.
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_BODY: usize = 1024 * 1024;
+
+/// An HTTP/1.x request, as read off a connection by [`Request::read`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// The target up to any `?`, e.g. `/search`.
+    pub path: String,
+    /// Whatever follows the `?`, still percent-encoded.
+    pub query: Option<String>,
+    /// `HTTP/1.0` or `HTTP/1.1`.
+    pub version: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// Header fields in the order they were received. Names are compared
+/// without regard to case, as HTTP requires.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    /// The value of the first field called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.0.push((name.to_string(), value.to_string()));
+    }
+}
+
+/// Why a request couldn't be read. All but `Io` deserve an error
+/// response, see [`ParseError::status_line`].
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    /// The bytes received aren't a valid HTTP request.
+    Malformed(String),
+    /// A version other than HTTP/1.0 or HTTP/1.1.
+    UnsupportedVersion(String),
+    /// A line, the headers or the body went past our limits.
+    TooLarge(&'static str),
+}
+
+impl ParseError {
+    pub fn status_line(&self) -> &'static str {
+        match self {
+            ParseError::Io(_) | ParseError::Malformed(_) => "HTTP/1.1 400 Bad Request",
+            ParseError::UnsupportedVersion(_) => "HTTP/1.1 505 HTTP Version Not Supported",
+            ParseError::TooLarge("request line") => "HTTP/1.1 414 URI Too Long",
+            ParseError::TooLarge("body") => "HTTP/1.1 413 Content Too Large",
+            ParseError::TooLarge(_) => "HTTP/1.1 431 Request Header Fields Too Large",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "{err}"),
+            ParseError::Malformed(reason) => write!(f, "malformed request: {reason}"),
+            ParseError::UnsupportedVersion(version) => write!(f, "unsupported version {version}"),
+            ParseError::TooLarge(what) => write!(f, "{what} too large"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        ParseError::Io(err)
+    }
+}
+
+fn malformed(reason: impl Into<String>) -> ParseError {
+    ParseError::Malformed(reason.into())
+}
+
+impl Request {
+    /// Reads one request, body included. Returns `None` if the client
+    /// closed the connection without sending anything.
+    pub fn read(reader: &mut impl BufRead) -> Result<Option<Request>, ParseError> {
+        let request_line = match read_line(reader, "request line")? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        let (method, target, version) = parse_request_line(&request_line)?;
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (target, None),
+        };
+
+        let headers = read_headers(reader)?;
+        if version == "HTTP/1.1" && headers.get("host").is_none() {
+            return Err(malformed("missing Host header"));
+        }
+        let body = read_body(reader, &headers)?;
+
+        Ok(Some(Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            version: version.to_string(),
+            headers,
+            body,
+        }))
+    }
+}
+
+/// What a handler answers with. `Content-Length` and `Connection: close`
+/// are added when it's written, since only one request is read per
+/// connection.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response {
+    /// E.g. `HTTP/1.1 200 OK`.
+    pub status_line: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status_line: &str, body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            status_line: status_line.to_string(),
+            headers: Headers::default(),
+            body: body.into(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("{}\r\n", self.status_line);
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        let length = self.body.len();
+        head.push_str(&format!(
+            "Content-Length: {length}\r\nConnection: close\r\n\r\n"
+        ));
+
+        out.write_all(head.as_bytes())?;
+        out.write_all(&self.body)
+    }
+}
+
+fn parse_request_line(line: &str) -> Result<(&str, &str, &str), ParseError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(malformed("request line isn't METHOD TARGET VERSION"));
+    };
+
+    if !is_token(method) {
+        return Err(malformed(format!("invalid method {method:?}")));
+    }
+    // Only the origin form (`/path?query`) and `*` make sense for a server
+    // that isn't a proxy.
+    let printable = target.bytes().all(|b| b.is_ascii_graphic());
+    if !(target.starts_with('/') || target == "*") || !printable {
+        return Err(malformed(format!("invalid target {target:?}")));
+    }
+    match version {
+        "HTTP/1.0" | "HTTP/1.1" => Ok((method, target, version)),
+        _ if is_version(version) => Err(ParseError::UnsupportedVersion(version.to_string())),
+        _ => Err(malformed(format!("invalid version {version:?}"))),
+    }
+}
+
+fn read_headers(reader: &mut impl BufRead) -> Result<Headers, ParseError> {
+    let mut headers = Headers::default();
+
+    loop {
+        let line =
+            read_line(reader, "header")?.ok_or_else(|| malformed("unexpected end of headers"))?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.0.len() == MAX_HEADERS {
+            return Err(ParseError::TooLarge("headers"));
+        }
+        if line.starts_with([' ', '\t']) {
+            return Err(malformed("obsolete header line folding"));
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| malformed(format!("header without a colon: {line:?}")))?;
+        // No whitespace is allowed between the name and the colon.
+        if !is_token(name) {
+            return Err(malformed(format!("invalid header name {name:?}")));
+        }
+        headers.insert(name, value.trim_matches([' ', '\t']));
+    }
+}
+
+fn read_body(reader: &mut impl BufRead, headers: &Headers) -> Result<Vec<u8>, ParseError> {
+    let transfer_encoding = headers.get("transfer-encoding");
+    let content_length = content_length(headers)?;
+
+    match (transfer_encoding, content_length) {
+        // Having both is a classic way to smuggle a second request past a
+        // proxy that reads the message differently.
+        (Some(_), Some(_)) => Err(malformed("both Transfer-Encoding and Content-Length")),
+        (Some(coding), None) if coding.eq_ignore_ascii_case("chunked") => read_chunked(reader),
+        (Some(coding), None) => Err(malformed(format!("unsupported transfer coding {coding:?}"))),
+        (None, Some(length)) if length > MAX_BODY => Err(ParseError::TooLarge("body")),
+        (None, Some(length)) => {
+            let mut body = vec![0; length];
+            reader
+                .read_exact(&mut body)
+                .map_err(|err| match err.kind() {
+                    io::ErrorKind::UnexpectedEof => malformed("body shorter than Content-Length"),
+                    _ => ParseError::Io(err),
+                })?;
+            Ok(body)
+        }
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+// Repeated Content-Length fields must all agree.
+fn content_length(headers: &Headers) -> Result<Option<usize>, ParseError> {
+    let mut length = None;
+    for value in headers.get_all("content-length") {
+        let parsed = value
+            .parse::<usize>()
+            .ok()
+            .filter(|_| value.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(|| malformed(format!("invalid Content-Length {value:?}")))?;
+        if length.is_some_and(|length| length != parsed) {
+            return Err(malformed("conflicting Content-Length values"));
+        }
+        length = Some(parsed);
+    }
+    Ok(length)
+}
+
+// Each chunk is its size in hex (maybe followed by `;extensions`), CRLF,
+// the data and another CRLF. A zero-size chunk ends the body, and is
+// followed by optional trailer fields and an empty line.
+fn read_chunked(reader: &mut impl BufRead) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+
+    loop {
+        let line = read_line(reader, "chunk size")?
+            .ok_or_else(|| malformed("unexpected end of chunks"))?;
+        let size = line.split(';').next().unwrap_or_default().trim_end();
+        let size = usize::from_str_radix(size, 16)
+            .ok()
+            .filter(|_| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| malformed(format!("invalid chunk size {line:?}")))?;
+        if size == 0 {
+            break;
+        }
+        // Compared this way round so that a huge size can't overflow.
+        if size > MAX_BODY - body.len() {
+            return Err(ParseError::TooLarge("body"));
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader
+            .read_exact(&mut body[start..])
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => malformed("chunk shorter than its size"),
+                _ => ParseError::Io(err),
+            })?;
+        if read_line(reader, "chunk")?.as_deref() != Some("") {
+            return Err(malformed("chunk data longer than its size"));
+        }
+    }
+
+    // Trailer fields are read like headers, but nothing here needs them.
+    read_headers(reader)?;
+    Ok(body)
+}
+
+// Reads a line ending in CRLF (or a bare LF, which clients get away with)
+// and returns it without the terminator, or `None` at the end of input.
+fn read_line(reader: &mut impl BufRead, what: &'static str) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(if line.len() as u64 + 1 >= MAX_LINE {
+            ParseError::TooLarge(what)
+        } else {
+            malformed(format!("{what} cut short"))
+        });
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| malformed(format!("{what} isn't valid UTF-8")))
+}
+
+// The characters RFC 9110 allows in methods and header names.
+fn is_token(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn is_version(text: &str) -> bool {
+    let Some(number) = text.strip_prefix("HTTP/") else {
+        return false;
+    };
+    let bytes = number.as_bytes();
+    bytes.len() == 3 && bytes[0].is_ascii_digit() && bytes[1] == b'.' && bytes[2].is_ascii_digit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Option<Request>, ParseError> {
+        Request::read(&mut raw.as_bytes())
+    }
+
+    fn status(raw: &str) -> &'static str {
+        parse(raw).unwrap_err().status_line()
+    }
+
+    #[test]
+    fn simple_get() {
+        let request = parse(
+            "GET /search?q=rust&page=2 HTTP/1.1\r\nHost: localhost\r\nX-Token:  abc \r\n\r\n",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!("GET", request.method);
+        assert_eq!("/search", request.path);
+        assert_eq!(Some("q=rust&page=2"), request.query.as_deref());
+        assert_eq!("HTTP/1.1", request.version);
+        assert_eq!(Some("localhost"), request.headers.get("HOST"));
+        assert_eq!(Some("abc"), request.headers.get("x-token"));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn content_length_body() {
+        let raw = "POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello, and more";
+        let request = parse(raw).unwrap().unwrap();
+        assert_eq!(b"hello", &request.body[..]);
+    }
+
+    #[test]
+    fn chunked_body() {
+        let raw = "POST /echo HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: Chunked\r\n\r\n\
+                   5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: x\r\n\r\n";
+        let request = parse(raw).unwrap().unwrap();
+        assert_eq!(b"hello, world", &request.body[..]);
+    }
+
+    #[test]
+    fn closed_without_a_request() {
+        assert!(parse("").unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let bad_request = "HTTP/1.1 400 Bad Request";
+        for raw in [
+            "GET /\r\n\r\n",
+            "GET  / HTTP/1.1\r\nHost: a\r\n\r\n",
+            "G(T / HTTP/1.1\r\nHost: a\r\n\r\n",
+            "GET http://example.com/ HTTP/1.1\r\nHost: a\r\n\r\n",
+            "GET / HTTX/1.1\r\nHost: a\r\n\r\n",
+            "GET / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost : a\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\nNo colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\n  folded\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nshort",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: -1\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n",
+        ] {
+            assert_eq!(bad_request, status(raw), "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn limits_and_versions() {
+        assert_eq!(
+            "HTTP/1.1 505 HTTP Version Not Supported",
+            status("GET / HTTP/2.0\r\n\r\n")
+        );
+
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE as usize));
+        assert_eq!("HTTP/1.1 414 URI Too Long", status(&long));
+
+        let header = format!(
+            "GET / HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_LINE as usize)
+        );
+        assert_eq!(
+            "HTTP/1.1 431 Request Header Fields Too Large",
+            status(&header)
+        );
+
+        let big = format!(
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert_eq!("HTTP/1.1 413 Content Too Large", status(&big));
+
+        let chunks = "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+                      1\r\na\r\nffffffffffffffff\r\n";
+        assert_eq!("HTTP/1.1 413 Content Too Large", status(chunks));
+
+        // HTTP/1.0 clients needn't send Host.
+        assert!(parse("GET / HTTP/1.0\r\n\r\n").unwrap().is_some());
+    }
+
+    #[test]
+    fn writes_responses() {
+        let mut out = Vec::new();
+        Response::new("HTTP/1.1 405 Method Not Allowed", "no")
+            .header("Allow", "GET")
+            .write_to(&mut out)
+            .unwrap();
+
+        let expected = "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\n\
+                        Content-Length: 2\r\nConnection: close\r\n\r\nno";
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+}
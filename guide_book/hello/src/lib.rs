@@ -1,3 +1,6 @@
+pub mod http;
+pub mod router;
+
 use std::{
     sync::{mpsc, Arc, Mutex},
     thread,
@@ -6,12 +9,23 @@ use std::{
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
+    verbose: bool,
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
 impl ThreadPool {
     pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::build(size, true)
+    }
+
+    /// Same as `new`, but the workers don't log their progress to stdout,
+    /// for programs whose stdout is their actual output.
+    pub fn silent(size: usize) -> ThreadPool {
+        ThreadPool::build(size, false)
+    }
+
+    fn build(size: usize, verbose: bool) -> ThreadPool {
         assert!(size > 0);
 
         let (sender, receiver) = mpsc::channel();
@@ -21,12 +35,13 @@ impl ThreadPool {
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), verbose));
         }
 
         ThreadPool {
             workers,
             sender: Some(sender),
+            verbose,
         }
     }
 
@@ -45,7 +60,9 @@ impl Drop for ThreadPool {
         drop(self.sender.take());
 
         for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
+            if self.verbose {
+                println!("Shutting down worker {}", worker.id);
+            }
 
             if let Some(thread) = worker.thread.take() {
                 thread.join().unwrap();
@@ -60,17 +77,21 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>, verbose: bool) -> Worker {
         let thread = thread::spawn(move || loop {
             let message = receiver.lock().unwrap().recv();
 
             match message {
                 Ok(job) => {
-                    println!("Worker {id} got a job; executing.");
+                    if verbose {
+                        println!("Worker {id} got a job; executing.");
+                    }
                     job();
                 }
                 Err(_) => {
-                    println!("Worker {id} disconnected; shutting down.");
+                    if verbose {
+                        println!("Worker {id} disconnected; shutting down.");
+                    }
                     break;
                 }
             }
@@ -1,8 +1,13 @@
-use hello::ThreadPool;
+use hello::{
+    http::{ParseError, Request, Response},
+    router::Router,
+    ThreadPool,
+};
 use std::{
     fs,
-    io::{prelude::*, BufReader},
+    io::BufReader,
     net::{TcpListener, TcpStream},
+    sync::Arc,
     thread,
     time::Duration,
 };
@@ -10,37 +15,52 @@ use std::{
 fn main() {
     let listener = TcpListener::bind("127.0.01:7878").unwrap();
     let pool = ThreadPool::new(4);
+    let router = Arc::new(routes());
 
     for stream in listener.incoming() {
         let stream = stream.unwrap();
+        let router = Arc::clone(&router);
 
-        pool.execute(|| {
-            handle_connection(stream);
+        pool.execute(move || {
+            handle_connection(stream, &router);
         });
     }
 
     println!("Shutting down.");
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-    // TODO: Gracefully handle errors
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
-
-    let (status_line, filename) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
+fn routes() -> Router {
+    let mut router = Router::new();
+    router
+        .get("/", |_, _| page("HTTP/1.1 200 OK", "hello.html"))
+        .get("/sleep", |_, _| {
             thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK", "hello.html")
-        }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
-    };
+            page("HTTP/1.1 200 OK", "hello.html")
+        })
+        .not_found(|_, _| page("HTTP/1.1 404 NOT FOUND", "404.html"));
+    router
+}
 
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
+fn page(status_line: &str, filename: &str) -> Response {
+    match fs::read(filename) {
+        Ok(contents) => Response::new(status_line, contents),
+        Err(err) => {
+            eprintln!("Failed to read {filename}: {err}");
+            Response::new("HTTP/1.1 500 Internal Server Error", "")
+        }
+    }
+}
 
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+fn handle_connection(mut stream: TcpStream, router: &Router) {
+    let mut buf_reader = BufReader::new(&mut stream);
+    let response = match Request::read(&mut buf_reader) {
+        Ok(Some(request)) => router.handle(&request),
+        // The client went away; there's no one to answer.
+        Ok(None) | Err(ParseError::Io(_)) => return,
+        Err(err) => Response::new(err.status_line(), format!("{err}\n")),
+    };
 
-    // TODO: Gracefully handle errors
-    stream.write_all(response.as_bytes()).unwrap();
+    if let Err(err) = response.write_to(&mut stream) {
+        eprintln!("Failed to respond: {err}");
+    }
 }
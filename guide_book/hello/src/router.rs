@@ -0,0 +1,270 @@
+use crate::http::{Request, Response};
+
+type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync>;
+
+/// Dispatches requests to handlers by method and path.
+///
+/// Patterns are matched segment by segment: `/users/:id` takes any single
+/// segment as `id`, and a final `/static/*path` takes everything after
+/// `/static/`, slashes included, as `path`. When several routes match, the
+/// one added first wins.
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Handler,
+}
+
+struct Route {
+    method: String,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+/// The parts of the path a route's `:name` and `*name` segments matched,
+/// as they appear in the request (still percent-encoded).
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl Router {
+    /// A router without routes, answering everything with a plain 404.
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            not_found: Box::new(|_, _| Response::new("HTTP/1.1 404 NOT FOUND", "Not Found\n")),
+        }
+    }
+
+    /// Adds a route for `method` requests whose path matches `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` doesn't start with `/`, has a `:` or `*` segment
+    /// without a name, or a `*` segment that isn't the last.
+    pub fn route<F>(&mut self, method: &str, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// Replaces the handler for paths no route matches.
+    pub fn not_found<F>(&mut self, handler: F) -> &mut Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Box::new(handler);
+        self
+    }
+
+    /// Runs the handler of the first route matching `request`. A path that
+    /// only matches routes for other methods gets a 405 listing them in
+    /// `Allow`.
+    pub fn handle(&self, request: &Request) -> Response {
+        let mut allowed: Vec<&str> = Vec::new();
+
+        for route in &self.routes {
+            let Some(params) = match_path(&route.pattern, &request.path) else {
+                continue;
+            };
+            if route.method == request.method {
+                return (route.handler)(request, &params);
+            }
+            if !allowed.contains(&route.method.as_str()) {
+                allowed.push(&route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            return (self.not_found)(request, &Params::default());
+        }
+        Response::new("HTTP/1.1 405 Method Not Allowed", "Method Not Allowed\n")
+            .header("Allow", &allowed.join(", "))
+    }
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let rest = pattern
+        .strip_prefix('/')
+        .unwrap_or_else(|| panic!("route pattern {pattern:?} doesn't start with '/'"));
+    let parts: Vec<&str> = rest.split('/').collect();
+
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            if let Some(name) = part.strip_prefix(':') {
+                assert!(!name.is_empty(), "unnamed parameter in {pattern:?}");
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                assert!(!name.is_empty(), "unnamed wildcard in {pattern:?}");
+                assert!(
+                    i == parts.len() - 1,
+                    "wildcard before the end of {pattern:?}"
+                );
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            }
+        })
+        .collect()
+}
+
+fn match_path(pattern: &[Segment], path: &str) -> Option<Params> {
+    let mut rest = path.strip_prefix('/')?;
+    let mut params = Params::default();
+
+    for (i, segment) in pattern.iter().enumerate() {
+        let last = i == pattern.len() - 1;
+        if let Segment::Wildcard(name) = segment {
+            // Like a parameter, a wildcard takes at least something.
+            if rest.is_empty() {
+                return None;
+            }
+            params.0.push((name.clone(), rest.to_string()));
+            return Some(params);
+        }
+
+        let (part, remainder) = match rest.split_once('/') {
+            Some((part, remainder)) if !last => (part, remainder),
+            Some(_) => return None,
+            None if last => (rest, ""),
+            None => return None,
+        };
+        match segment {
+            Segment::Literal(literal) if literal == part => {}
+            Segment::Param(name) if !part.is_empty() => {
+                params.0.push((name.clone(), part.to_string()))
+            }
+            _ => return None,
+        }
+        rest = remainder;
+    }
+    Some(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str) -> Request {
+        let raw = format!("{method} {path} HTTP/1.0\r\n\r\n");
+        Request::read(&mut raw.as_bytes()).unwrap().unwrap()
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(&response.body).unwrap()
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router
+            .get("/", |_, _| Response::new("HTTP/1.1 200 OK", "home"))
+            .get("/users/new", |_, _| {
+                Response::new("HTTP/1.1 200 OK", "form")
+            })
+            .get("/users/:id", |_, params| {
+                Response::new(
+                    "HTTP/1.1 200 OK",
+                    format!("user {}", params.get("id").unwrap()),
+                )
+            })
+            .post("/users/:id", |_, _| {
+                Response::new("HTTP/1.1 200 OK", "updated")
+            })
+            .get("/static/*path", |_, params| {
+                Response::new("HTTP/1.1 200 OK", params.get("path").unwrap())
+            });
+        router
+    }
+
+    #[test]
+    fn dispatches_by_method_and_path() {
+        let router = router();
+
+        assert_eq!("home", body(&router.handle(&request("GET", "/"))));
+        assert_eq!("form", body(&router.handle(&request("GET", "/users/new"))));
+        assert_eq!(
+            "user 42",
+            body(&router.handle(&request("GET", "/users/42?x=1")))
+        );
+        assert_eq!(
+            "updated",
+            body(&router.handle(&request("POST", "/users/42")))
+        );
+        assert_eq!(
+            "css/site.css",
+            body(&router.handle(&request("GET", "/static/css/site.css")))
+        );
+    }
+
+    #[test]
+    fn not_found() {
+        let router = router();
+
+        for path in ["/nope", "/users", "/users/", "/users/42/posts", "/static/"] {
+            let response = router.handle(&request("GET", path));
+            assert_eq!("HTTP/1.1 404 NOT FOUND", response.status_line, "{path}");
+        }
+
+        let mut router = Router::new();
+        router.not_found(|request, _| Response::new("HTTP/1.1 404 NOT FOUND", &*request.path));
+        assert_eq!("/gone", body(&router.handle(&request("GET", "/gone"))));
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let router = router();
+
+        let response = router.handle(&request("DELETE", "/users/42"));
+        assert_eq!("HTTP/1.1 405 Method Not Allowed", response.status_line);
+        assert_eq!(Some("GET, POST"), response.headers.get("allow"));
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard before the end")]
+    fn wildcard_must_be_last() {
+        Router::new().get("/files/*path/raw", |_, _| {
+            Response::new("HTTP/1.1 200 OK", "")
+        });
+    }
+}